@@ -0,0 +1,233 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{bail, Context};
+use awedio::manager::Manager;
+use image::RgbaImage;
+use ratatui::buffer::Buffer;
+use ratatui::layout::Rect;
+use ratatui::prelude::{Color, Stylize, Widget};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, Clear, Paragraph, Wrap};
+use resvg::{tiny_skia, usvg};
+
+/// Vector images are drawn this wide, which is plenty for a terminal.
+const SVG_WIDTH: u32 = 1024;
+
+/// A file joined to an email, loaded when the emails are.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub name: String,
+    path: PathBuf,
+    content: Content,
+}
+
+#[derive(Debug, Clone)]
+enum Content {
+    Text(String),
+    Audio,
+    Image(Arc<RgbaImage>),
+}
+
+impl Attachment {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let extension = path.extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let content = match extension.as_str() {
+            "mp3" | "wav" | "ogg" | "flac" => {
+                if !path.is_file() {
+                    bail!("{} not found", path.display());
+                }
+                Content::Audio
+            }
+            "svg" => Content::Image(Arc::new(load_svg(path)?)),
+            "png" | "jpg" | "jpeg" => {
+                let image = image::open(path).with_context(|| format!("could not read {}", path.display()))?;
+                Content::Image(Arc::new(image.to_rgba8()))
+            }
+            _ => Content::Text(std::fs::read_to_string(path)
+                .with_context(|| format!("could not read {}", path.display()))?),
+        };
+
+        Ok(Self {
+            name: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            path: path.to_path_buf(),
+            content,
+        })
+    }
+
+    /// Called when the players open the attachment. Audio starts playing.
+    pub fn open(&self, manager: &Manager) {
+        if let Content::Audio = self.content {
+            if let Ok(sound) = awedio::sounds::open_file(&self.path) {
+                manager.clone().play(sound);
+            }
+        }
+    }
+
+    /// How many lines of text the viewer can scroll through at `width`.
+    pub fn line_count(&self, width: u16) -> usize {
+        match &self.content {
+            Content::Text(text) => Paragraph::new(text.as_str()).wrap(Wrap { trim: false }).line_count(width),
+            _ => 0,
+        }
+    }
+}
+
+fn load_svg(path: &Path) -> anyhow::Result<RgbaImage> {
+    let data = std::fs::read(path).with_context(|| format!("could not read {}", path.display()))?;
+    let tree = usvg::Tree::from_data(&data, &usvg::Options::default())
+        .with_context(|| format!("{} is not a valid SVG", path.display()))?;
+
+    let size = tree.size();
+    let scale = SVG_WIDTH as f32 / size.width();
+    let height = (size.height() * scale).ceil() as u32;
+    let Some(mut pixmap) = tiny_skia::Pixmap::new(SVG_WIDTH, height.max(1)) else {
+        bail!("{} is empty", path.display());
+    };
+    // Sheet music is black on nothing, which would be black on black in the terminal
+    pixmap.fill(tiny_skia::Color::WHITE);
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    RgbaImage::from_raw(pixmap.width(), pixmap.height(), pixmap.take())
+        .context("the rendered SVG has the wrong size")
+}
+
+/// Shows an opened attachment, over what's in `area`.
+pub struct Viewer<'a> {
+    pub attachment: &'a Attachment,
+    pub scroll: u16,
+}
+
+impl Widget for Viewer<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let block = Block::bordered()
+            .title(self.attachment.name.as_str())
+            .title_bottom("<ÉCHAP> fermer")
+            .yellow();
+        let inner = block.inner(area);
+        Clear.render(area, buf);
+        block.render(area, buf);
+
+        match &self.attachment.content {
+            Content::Text(text) => {
+                Paragraph::new(text.as_str())
+                    .wrap(Wrap { trim: false })
+                    .scroll((self.scroll, 0))
+                    .reset()
+                    .render(inner, buf);
+            }
+            Content::Audio => {
+                let lines = vec![
+                    Line::from(format!("♪ {}", self.attachment.name)),
+                    Line::raw(""),
+                    Line::from("<ESPACE> réécouter").dim(),
+                ];
+                let top = inner.height.saturating_sub(lines.len() as u16) / 2;
+                let area = Rect { y: inner.y + top, height: inner.height - top, ..inner };
+                Paragraph::new(Text::from(lines)).centered().reset().render(area, buf);
+            }
+            Content::Image(image) => render_image(image, inner, buf),
+        }
+    }
+}
+
+/// Draws two pixels per cell, one above the other, so that they come out about square.
+fn render_image(image: &RgbaImage, area: Rect, buf: &mut Buffer) {
+    if area.is_empty() || image.width() == 0 || image.height() == 0 {
+        return;
+    }
+    let scale = f64::min(
+        area.width as f64 / image.width() as f64,
+        area.height as f64 * 2.0 / image.height() as f64,
+    );
+    let width = ((image.width() as f64 * scale) as u32).clamp(1, area.width as u32);
+    let height = ((image.height() as f64 * scale) as u32).clamp(1, area.height as u32 * 2);
+    let small = image::imageops::thumbnail(image, width, height);
+
+    let left = area.x + (area.width - width as u16) / 2;
+    let top = area.y + (area.height - height.div_ceil(2) as u16) / 2;
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let color = |y: u32| {
+                let [r, g, b, _] = small.get_pixel(x, y).0;
+                Color::Rgb(r, g, b)
+            };
+            let bottom = if y + 1 < height { color(y + 1) } else { Color::Reset };
+            buf[(left + x as u16, top + (y / 2) as u16)]
+                .set_symbol("▀")
+                .set_fg(color(y))
+                .set_bg(bottom);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What the viewer shows of the attachment, one line per row.
+    fn view(attachment: &Attachment, width: u16, height: u16) -> Buffer {
+        let mut buf = Buffer::empty(Rect::new(0, 0, width, height));
+        Viewer { attachment, scroll: 0 }.render(buf.area, &mut buf);
+        buf
+    }
+
+    fn rows(buf: &Buffer) -> Vec<String> {
+        buf.content()
+            .chunks(buf.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn text() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("notes.txt");
+        std::fs::write(&path, "ligne une\nligne deux, bien plus longue").unwrap();
+        let attachment = Attachment::load(&path).unwrap();
+        assert_eq!(attachment.name, "notes.txt");
+        assert_eq!(attachment.line_count(30), 2);
+        assert_eq!(attachment.line_count(10), 5);
+
+        let rows = rows(&view(&attachment, 32, 5));
+        assert!(rows[0].starts_with("┌notes.txt"));
+        assert_eq!(rows[1], "│ligne une                     │");
+        assert_eq!(rows[2], "│ligne deux, bien plus longue  │");
+        assert!(rows[4].starts_with("└<ÉCHAP> fermer"));
+    }
+
+    #[test]
+    fn svg() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("partition.svg");
+        // Red on the left, blank on the right
+        std::fs::write(&path, r##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+            <rect x="0" y="0" width="10" height="10" fill="#ff0000"/>
+        </svg>"##).unwrap();
+        let attachment = Attachment::load(&path).unwrap();
+        let Content::Image(image) = &attachment.content else {
+            panic!("{:?} is not an image", attachment.content);
+        };
+        assert_eq!(image.dimensions(), (SVG_WIDTH, SVG_WIDTH / 2));
+        assert_eq!(attachment.line_count(10), 0);
+
+        // 20 pixels wide and 10 high fit the 20 by 5 cells inside the borders
+        let buf = view(&attachment, 22, 7);
+        let left = &buf[(1, 1)];
+        let right = &buf[(20, 5)];
+        assert_eq!(left.symbol(), "▀");
+        assert_eq!((left.fg, left.bg), (Color::Rgb(255, 0, 0), Color::Rgb(255, 0, 0)));
+        assert_eq!((right.fg, right.bg), (Color::Rgb(255, 255, 255), Color::Rgb(255, 255, 255)));
+
+        std::fs::write(&path, "<svg").unwrap();
+        assert!(Attachment::load(&path).unwrap_err().to_string().ends_with("partition.svg is not a valid SVG"));
+    }
+
+    #[test]
+    fn audio() {
+        let attachment = Attachment::load(Path::new("confirm.wav")).unwrap();
+        assert!(rows(&view(&attachment, 30, 7)).iter().any(|x| x.contains("♪ confirm.wav")));
+        assert_eq!(Attachment::load(Path::new("absent.wav")).unwrap_err().to_string(), "absent.wav not found");
+    }
+}
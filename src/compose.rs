@@ -0,0 +1,267 @@
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::prelude::{Line, Span, Stylize, Widget};
+use ratatui::symbols;
+use ratatui::text::ToSpan;
+use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
+use serde::{Deserialize, Serialize};
+use crate::email::{find_all, fold_str};
+
+/// What happens when the players send an email, from the `[[tab.rule]]` tables of an email tab.
+/// The first rule the email matches applies:
+///
+/// ```toml
+/// [[tab.rule]]
+/// to = "lucas.morel@isras.ch"
+/// body = "ALMA.PAIX"
+/// reply = "lucas-merci"
+/// goto = "decrypt"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// One of the recipients, in any case. Without it, any address will do.
+    pub to: Option<String>,
+    /// Text the subject must contain, ignoring case and accents.
+    pub subject: Option<String>,
+    /// Text the body must contain, ignoring case and accents.
+    pub body: Option<String>,
+    /// An email of the folder that arrives in answer, usually written with `deliver: manual`.
+    pub reply: Option<String>,
+    /// The tab shown once the email is sent.
+    pub goto: Option<String>,
+    /// Whether sending the email solves the puzzle of the email tab, installing its fragment.
+    #[serde(default)]
+    pub solves: bool,
+}
+
+impl Rule {
+    pub fn matches(&self, email: &SentEmail) -> bool {
+        let contains = |text: &str, part: &Option<String>| {
+            let part = part.as_deref().map(str::trim).unwrap_or_default();
+            part.is_empty() || !find_all(&fold_str(text), &fold_str(part)).is_empty()
+        };
+        let to = self.to.as_deref()
+            .is_none_or(|to| email.recipients().any(|x| x.eq_ignore_ascii_case(to.trim())));
+        to && contains(&email.subject, &self.subject) && contains(&email.body, &self.body)
+    }
+}
+
+/// An email written by the players.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SentEmail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl SentEmail {
+    /// The addresses in `to`, separated by commas.
+    pub fn recipients(&self) -> impl Iterator<Item=&str> {
+        self.to.split([',', ';']).map(str::trim).filter(|x| !x.is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum Field {
+    #[default]
+    To,
+    Subject,
+    Body,
+    Send,
+}
+
+impl Field {
+    fn next(self) -> Self {
+        match self {
+            Field::To => Field::Subject,
+            Field::Subject => Field::Body,
+            Field::Body => Field::Send,
+            Field::Send => Field::To,
+        }
+    }
+
+    fn previous(self) -> Self {
+        match self {
+            Field::To => Field::Send,
+            Field::Subject => Field::To,
+            Field::Body => Field::Subject,
+            Field::Send => Field::Body,
+        }
+    }
+}
+
+/// An email being written.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Draft {
+    pub email: SentEmail,
+    field: Field,
+}
+
+/// What a key did to the draft.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Writing,
+    Cancel,
+    Send,
+}
+
+impl Draft {
+    pub fn handle_input(&mut self, key: KeyEvent) -> Edit {
+        let text = match self.field {
+            Field::To => Some(&mut self.email.to),
+            Field::Subject => Some(&mut self.email.subject),
+            Field::Body => Some(&mut self.email.body),
+            Field::Send => None,
+        };
+        match (key.code, text) {
+            (KeyCode::Esc, _) => return Edit::Cancel,
+            (KeyCode::Tab, _) => self.field = self.field.next(),
+            (KeyCode::BackTab, _) => self.field = self.field.previous(),
+            (KeyCode::Enter, None) => {
+                if self.email.recipients().next().is_some() {
+                    return Edit::Send;
+                }
+                // Nobody to send it to
+                self.field = Field::To;
+            }
+            (KeyCode::Enter, Some(text)) if self.field == Field::Body => text.push('\n'),
+            (KeyCode::Enter, Some(_)) => self.field = self.field.next(),
+            (KeyCode::Char(c), Some(text)) if !key.modifiers.contains(KeyModifiers::CONTROL) => text.push(c),
+            (KeyCode::Backspace, Some(text)) => {
+                text.pop();
+            }
+            _ => {}
+        }
+        Edit::Writing
+    }
+}
+
+/// The draft, drawn like the emails it sits next to.
+pub struct ComposeForm<'a> {
+    pub draft: &'a Draft,
+}
+
+impl Widget for ComposeForm<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [to, subject, body, send] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Length(2),
+            Constraint::Fill(1),
+            Constraint::Length(3),
+        ]).areas(area);
+
+        let border_set_top = symbols::border::Set {
+            top_left: symbols::line::NORMAL.horizontal_down,
+            ..symbols::border::Set::default()
+        };
+        let border_set_mid = symbols::border::Set {
+            top_left: symbols::line::NORMAL.vertical_right,
+            top_right: symbols::line::NORMAL.vertical_left,
+            ..symbols::border::Set::default()
+        };
+        let border_set_bottom = symbols::border::Set {
+            top_left: symbols::line::NORMAL.vertical_right,
+            top_right: symbols::line::NORMAL.vertical_left,
+            bottom_left: symbols::line::NORMAL.horizontal_up,
+            ..symbols::border::Set::default()
+        };
+
+        let field = self.draft.field;
+        let focused = |block: Block<'static>, x: Field| if x == field { block.green() } else { block };
+        let cursor = |x: Field| if x == field { "_" } else { "" };
+        let email = &self.draft.email;
+
+        Paragraph::new(Line::from(vec![
+            "à: ".to_span().dim().bold(),
+            email.to.to_span(),
+            cursor(Field::To).to_span(),
+        ])).block(focused(Block::new()
+            .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT)
+            .border_set(border_set_top)
+            .title("Nouveau courriel")
+            .title(Line::from("<TAB> champ suivant  <ÉCHAP> annuler").right_aligned()), Field::To)
+        ).render(to, buf);
+
+        let mid_block = Block::new()
+            .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT)
+            .border_set(border_set_mid);
+        Paragraph::new(Line::from(vec![
+            "sujet: ".to_span().dim().bold(),
+            email.subject.to_span(),
+            cursor(Field::Subject).to_span(),
+        ])).block(focused(mid_block.clone(), Field::Subject)).render(subject, buf);
+
+        // The end of the body stays in sight while typing
+        let block = focused(mid_block, Field::Body);
+        let inner = block.inner(body);
+        let text = format!("{}{}", email.body, cursor(Field::Body));
+        let par = Paragraph::new(text).wrap(Wrap { trim: false });
+        let scroll = par.line_count(inner.width).saturating_sub(inner.height as usize);
+        par.scroll((scroll.try_into().unwrap_or(u16::MAX), 0)).block(block).render(body, buf);
+
+        let button = Span::raw("[ Envoyer ]");
+        let button = if field == Field::Send { button.reversed() } else { button };
+        Paragraph::new(button)
+            .centered()
+            .block(focused(Block::new().borders(Borders::all()).border_set(border_set_bottom), Field::Send))
+            .render(send, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(source: &str) -> Rule {
+        toml::from_str(source).unwrap()
+    }
+
+    fn email(to: &str, subject: &str, body: &str) -> SentEmail {
+        SentEmail { to: to.to_string(), subject: subject.to_string(), body: body.to_string() }
+    }
+
+    #[test]
+    fn recipients() {
+        let rule = rule(r#"to = " Lucas.Morel@isras.ch ""#);
+        assert!(rule.matches(&email("lucas.morel@isras.ch", "", "")));
+        assert!(rule.matches(&email("eve.chavanne@isras.ch; LUCAS.MOREL@ISRAS.CH", "", "")));
+        assert!(rule.matches(&email(" , lucas.morel@isras.ch,", "", "")));
+        assert!(!rule.matches(&email("eve.chavanne@isras.ch", "", "")));
+        // Whole addresses only
+        assert!(!rule.matches(&email("lucas.morel@isras.ch.fr", "", "")));
+    }
+
+    #[test]
+    fn subject_and_body() {
+        let rule = rule(r#"
+            subject = "Clé"
+            body = "alma.paix"
+        "#);
+        assert!(rule.matches(&email("qui@importe.ch", "Re: les clés", "La clé est ALMA.PAIX, je crois")));
+        assert!(rule.matches(&email("qui@importe.ch", "CLE", "alma.paix")));
+        assert!(!rule.matches(&email("qui@importe.ch", "Clé", "alma paix")));
+        assert!(!rule.matches(&email("qui@importe.ch", "Bonjour", "alma.paix")));
+        // The body isn't searched for the subject, nor the other way around
+        assert!(!rule.matches(&email("qui@importe.ch", "alma.paix", "clé")));
+    }
+
+    #[test]
+    fn every_condition_must_hold() {
+        let rule = rule(r#"
+            to = "lucas.morel@isras.ch"
+            body = "2.5"
+        "#);
+        assert!(rule.matches(&email("lucas.morel@isras.ch", "", "réponse : 2.5")));
+        assert!(!rule.matches(&email("eve.chavanne@isras.ch", "", "réponse : 2.5")));
+        assert!(!rule.matches(&email("lucas.morel@isras.ch", "", "réponse : 25")));
+    }
+
+    #[test]
+    fn empty_conditions_match_anything() {
+        assert!(rule("").matches(&email("qui@importe.ch", "", "")));
+        assert!(rule(r#"subject = "  ""#).matches(&email("qui@importe.ch", "Bonjour", "")));
+        assert!(toml::from_str::<Rule>(r#"sujet = "Clé""#).is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::Duration;
+use anyhow::Context;
+use crate::timer::parse_duration;
+
+/// Commands the game master can send from the control desk, one per line:
+///
+/// ```text
+/// goto <tab name>
+/// plug <fragment>
+/// hint [text]
+/// time <+|-><number><s|m>
+/// deliver <email>
+/// pause
+/// resume
+/// reset
+/// quit
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Goto(String),
+    Plug(usize),
+    Hint(String),
+    /// Reveals the next hint of the current tab
+    NextHint,
+    /// Adds or removes seconds from the timer
+    Time(i64),
+    /// Puts the email with this file name in the inbox, whatever its `Deliver` header says
+    Deliver(String),
+    Pause,
+    Resume,
+    Reset,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+        let argument = argument.trim();
+        match name {
+            "goto" if !argument.is_empty() => Ok(Command::Goto(argument.to_string())),
+            "plug" => argument.parse()
+                .map(Command::Plug)
+                .map_err(|_| format!("`{}` is not a fragment number", argument)),
+            "hint" if argument.is_empty() => Ok(Command::NextHint),
+            "hint" => Ok(Command::Hint(argument.to_string())),
+            "time" => parse_seconds(argument)
+                .map(Command::Time)
+                .ok_or_else(|| format!("`{}` is not a duration like `+5m` or `-30s`", argument)),
+            "deliver" if !argument.is_empty() => Ok(Command::Deliver(argument.to_string())),
+            "pause" => Ok(Command::Pause),
+            "resume" => Ok(Command::Resume),
+            "reset" => Ok(Command::Reset),
+            "quit" => Ok(Command::Quit),
+            "goto" | "deliver" => Err(format!("`{}` needs an argument", name)),
+            _ => Err(format!("unknown command `{}`", name)),
+        }
+    }
+}
+
+fn parse_seconds(text: &str) -> Option<i64> {
+    let (sign, duration) = match text.strip_prefix('-') {
+        Some(duration) => (-1, duration),
+        None => (1, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let seconds: i64 = parse_duration(duration)?.as_secs().try_into().ok()?;
+    Some(sign * seconds)
+}
+
+/// A command waiting for the app to carry it out.
+pub struct Request {
+    pub command: Command,
+    reply: mpsc::Sender<Result<(), String>>,
+}
+
+impl Request {
+    pub fn reply(self, result: Result<(), String>) {
+        // The game master may already have disconnected, which is fine
+        let _ = self.reply.send(result);
+    }
+}
+
+/// Listens for game master connections on `address`. Commands are handed to the app through the
+/// returned receiver, which it polls alongside the keyboard.
+pub fn listen(address: &str) -> anyhow::Result<mpsc::Receiver<Request>> {
+    let listener = TcpListener::bind(address)
+        .with_context(|| format!("could not open the control socket on {}", address))?;
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let tx = tx.clone();
+            std::thread::spawn(move || serve(stream, tx));
+        }
+    });
+
+    Ok(rx)
+}
+
+fn serve(stream: TcpStream, requests: mpsc::Sender<Request>) {
+    let Ok(mut output) = stream.try_clone() else {
+        return;
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = match line.parse() {
+            Ok(command) => {
+                let (reply, response) = mpsc::channel();
+                if requests.send(Request { command, reply }).is_err() {
+                    // The app is gone
+                    return;
+                }
+                response.recv_timeout(Duration::from_secs(5))
+                    .unwrap_or_else(|_| Err("the room did not answer".to_string()))
+            }
+            Err(e) => Err(e),
+        };
+
+        let written = match result {
+            Ok(()) => writeln!(output, "ok"),
+            Err(e) => writeln!(output, "error: {}", e),
+        };
+        if written.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        assert_eq!("goto email".parse(), Ok(Command::Goto("email".to_string())));
+        assert_eq!("  plug 3 ".parse(), Ok(Command::Plug(3)));
+        assert_eq!("deliver aa-corrupted-1".parse(), Ok(Command::Deliver("aa-corrupted-1".to_string())));
+        assert_eq!("pause".parse(), Ok(Command::Pause));
+        assert_eq!("resume".parse(), Ok(Command::Resume));
+        assert_eq!("reset".parse(), Ok(Command::Reset));
+        assert_eq!("quit".parse(), Ok(Command::Quit));
+    }
+
+    #[test]
+    fn hints() {
+        assert_eq!("hint".parse(), Ok(Command::NextHint));
+        assert_eq!("hint  ".parse(), Ok(Command::NextHint));
+        assert_eq!("hint Regardez sous le clavier".parse(), Ok(Command::Hint("Regardez sous le clavier".to_string())));
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!("time +90s".parse(), Ok(Command::Time(90)));
+        assert_eq!("time 90s".parse(), Ok(Command::Time(90)));
+        assert_eq!("time -5m".parse(), Ok(Command::Time(-300)));
+        assert_eq!("time +2m".parse(), Ok(Command::Time(120)));
+        assert_eq!("time 5h".parse::<Command>(), Err("`5h` is not a duration like `+5m` or `-30s`".to_string()));
+        // The unit is always written
+        assert_eq!("time +90".parse::<Command>(), Err("`+90` is not a duration like `+5m` or `-30s`".to_string()));
+        assert!("time +-5m".parse::<Command>().is_err());
+        assert!("time".parse::<Command>().is_err());
+        assert!(format!("time {}m", i64::MAX).parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("dance".parse::<Command>(), Err("unknown command `dance`".to_string()));
+        assert_eq!("goto".parse::<Command>(), Err("`goto` needs an argument".to_string()));
+        assert_eq!("deliver ".parse::<Command>(), Err("`deliver` needs an argument".to_string()));
+        assert_eq!("plug deux".parse::<Command>(), Err("`deux` is not a fragment number".to_string()));
+    }
+}
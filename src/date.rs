@@ -0,0 +1,144 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use anyhow::{bail, Result};
+use chrono::format::{DelayedFormat, StrftimeItems};
+use chrono::{Locale, NaiveDate, NaiveDateTime, NaiveTime};
+
+/// When an email was sent, from its `Date` header: `1985-04-20`, with the time if it matters,
+/// `1985-04-20 14:32` or `1985-04-20 (14:32)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EmailDate {
+    date: NaiveDate,
+    // Emails without one come first on their day
+    time: Option<NaiveTime>,
+}
+
+impl FromStr for EmailDate {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let (date, time) = text.split_once(' ').unwrap_or((text, ""));
+        let time = time.trim().trim_start_matches('(').trim_end_matches(')').trim();
+        let error = || format!("`{}` is not a date like `1985-04-20` or `1985-04-20 14:32`", text);
+
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| error())?;
+        let time = match time {
+            "" => None,
+            time => Some(NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| error())?),
+        };
+        Ok(EmailDate { date, time })
+    }
+}
+
+/// How dates are shown, from the `date_format` and `date_time_format` of an email tab, written like
+/// [strftime](https://docs.rs/chrono/latest/chrono/format/strftime/index.html) with French names.
+#[derive(Debug, Clone)]
+pub struct DateFormat {
+    date: String,
+    date_time: String,
+}
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        Self {
+            // 20 avril 1985
+            date: "%-d %B %Y".to_string(),
+            // 20 avril 1985, 14:32
+            date_time: "%-d %B %Y, %H:%M".to_string(),
+        }
+    }
+}
+
+impl DateFormat {
+    pub fn new(date: Option<String>, date_time: Option<String>) -> Result<Self> {
+        let default = Self::default();
+        let format = Self {
+            date: date.unwrap_or(default.date),
+            date_time: date_time.unwrap_or(default.date_time),
+        };
+        // Formatting a date is the only way to tell if it asks for something dates don't have
+        let sample = EmailDate {
+            date: NaiveDate::from_ymd_opt(1985, 4, 20).unwrap(),
+            time: NaiveTime::from_hms_opt(14, 32, 0),
+        };
+        for (text, date) in [(&format.date, EmailDate { time: None, ..sample }), (&format.date_time, sample)] {
+            if write!(String::new(), "{}", format.formatted(&date)).is_err() {
+                bail!("`{}` is not a date format", text);
+            }
+        }
+        Ok(format)
+    }
+
+    pub fn show(&self, date: &EmailDate) -> String {
+        self.formatted(date).to_string()
+    }
+
+    fn formatted(&self, date: &EmailDate) -> DelayedFormat<StrftimeItems<'_>> {
+        match date.time {
+            Some(time) => NaiveDateTime::new(date.date, time).and_utc().format_localized(&self.date_time, Locale::fr_FR),
+            None => date.date.format_localized(&self.date, Locale::fr_FR),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> EmailDate {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(date("1985-04-20"), EmailDate { date: NaiveDate::from_ymd_opt(1985, 4, 20).unwrap(), time: None });
+        let with_time = EmailDate { date: NaiveDate::from_ymd_opt(1985, 4, 20).unwrap(), time: NaiveTime::from_hms_opt(14, 32, 0) };
+        assert_eq!(date("1985-04-20 14:32"), with_time);
+        assert_eq!(date(" 1985-04-20 (14:32) "), with_time);
+        assert_eq!(date("1985-04-20  ( 14:32 )"), with_time);
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for text in ["20/04/1985", "1985-02-30", "1985-04-20 25:00", "1985-04-20 14h32", "1985-04-20 14:32:10", ""] {
+            assert_eq!(text.parse::<EmailDate>(), Err(format!("`{}` is not a date like `1985-04-20` or `1985-04-20 14:32`", text)));
+        }
+    }
+
+    #[test]
+    fn order() {
+        let mut dates = vec![
+            Some(date("1985-04-20 09:00")),
+            None,
+            Some(date("1985-04-20")),
+            Some(date("1985-04-15 18:20")),
+            Some(date("1985-04-20 (08:59)")),
+        ];
+        dates.sort();
+        // Undated emails come before all the others, and those without a time first on their day
+        assert_eq!(dates, vec![
+            None,
+            Some(date("1985-04-15 18:20")),
+            Some(date("1985-04-20")),
+            Some(date("1985-04-20 08:59")),
+            Some(date("1985-04-20 09:00")),
+        ]);
+    }
+
+    #[test]
+    fn formats() {
+        let default = DateFormat::default();
+        assert_eq!(default.show(&date("1985-04-20")), "20 avril 1985");
+        assert_eq!(default.show(&date("1985-08-02 09:05")), "2 août 1985, 09:05");
+
+        let format = DateFormat::new(Some("%A %d/%m/%y".to_string()), None).unwrap();
+        assert_eq!(format.show(&date("1985-04-20")), "samedi 20/04/85");
+        assert_eq!(format.show(&date("1985-04-20 14:32")), "20 avril 1985, 14:32");
+
+        assert_eq!(DateFormat::new(Some("%Q".to_string()), None).unwrap_err().to_string(), "`%Q` is not a date format");
+        // Dates alone have no time to show
+        assert_eq!(DateFormat::new(Some("%H:%M".to_string()), None).unwrap_err().to_string(), "`%H:%M` is not a date format");
+        assert!(DateFormat::new(None, Some("%d %B, %Hh%M".to_string())).is_ok());
+    }
+}
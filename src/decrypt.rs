@@ -7,9 +7,11 @@ use ratatui::symbols;
 use ratatui::symbols::border;
 use ratatui::text::ToLine;
 use ratatui::widgets::{Paragraph, StatefulWidgetRef};
-use crate::model::Tab;
+use crate::model::{Tab, Transition};
+use crate::session_log;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DecryptState {
     entry: String,
     error: bool,
@@ -25,11 +27,14 @@ impl DecryptState {
 }
 
 #[derive(Debug, Clone)]
-pub struct Decrypt;
+pub struct Decrypt {
+    cipher: String,
+    answer: String,
+}
 
 impl Decrypt {
-    pub fn new() -> Decrypt {
-        Decrypt
+    pub fn new(cipher: String, answer: String) -> Decrypt {
+        Decrypt { cipher, answer }
     }
 }
 
@@ -85,7 +90,7 @@ impl StatefulWidgetRef for Decrypt {
             .block(block)
             .render(layout2[2], buf);
 
-        Paragraph::new("ÉVLWÉÈJDJ".to_string().to_line().left_aligned())
+        Paragraph::new(self.cipher.to_line().left_aligned())
             .centered()
             .block(block_top)
             .render(layout2[1], buf);
@@ -93,7 +98,7 @@ impl StatefulWidgetRef for Decrypt {
 }
 
 impl Tab for Decrypt {
-    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<usize> {
+    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<Transition> {
         if let Event::Key(KeyEvent { code: KeyCode::Char(c), kind: KeyEventKind::Press, .. }) = event {
             if state.entry.len() < 20 {
                 state.entry.push(c);
@@ -103,8 +108,10 @@ impl Tab for Decrypt {
             state.entry.pop();
             state.error = false;
         } else if let Event::Key(KeyEvent { code: KeyCode::Enter, kind: KeyEventKind::Press, .. }) = event {
-            if state.entry == "ALMA.PAIX".to_string() {
-                return Some(1); // Email program
+            let correct = state.entry == self.answer;
+            session_log::attempt("decrypt", &state.entry, correct);
+            if correct {
+                return Some(Transition::Solved);
             } else {
                 state.error = true;
             }
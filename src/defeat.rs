@@ -0,0 +1,28 @@
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Constraint, Flex, Layout, Rect};
+use ratatui::prelude::{Line, Stylize, Widget};
+use tui_big_text::{BigText, PixelSize};
+
+/// Shown instead of everything else when the timer runs out.
+#[derive(Debug, Clone)]
+pub struct Defeat;
+
+impl Widget for &Defeat {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [title, subtitle] = Layout::vertical([Constraint::Length(8), Constraint::Length(1)])
+            .spacing(2)
+            .flex(Flex::Center)
+            .areas(area);
+
+        BigText::builder()
+            .pixel_size(PixelSize::HalfHeight)
+            .lines(vec!["TEMPS".red().into(), "ECOULE".red().into()])
+            .centered()
+            .build()
+            .render(title, buf);
+        Line::from("Le module pacifiste est perdu.")
+            .red()
+            .centered()
+            .render(subtitle, buf);
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::model::Routes;
+use crate::scenario::FRAGMENT_COUNT;
+use crate::timer::parse_duration;
+
+/// When an email reaches the inbox, from its `Deliver` header:
+///
+/// ```text
+/// deliver: fragment 2      once the second fragment is installed
+/// deliver: solved decrypt  once the tab named `decrypt` is solved
+/// deliver: after 15m       this long into the game, in minutes or seconds (`90s`)
+/// deliver: manual          when the game master sends `deliver <email>`
+/// ```
+///
+/// Emails without the header are there from the start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delivery {
+    Immediate,
+    /// Counting from 0, like `usbs_plugged`
+    Fragment(usize),
+    Solved(String),
+    After(Duration),
+    Manual,
+}
+
+impl FromStr for Delivery {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let (name, argument) = text.split_once(' ').unwrap_or((text, ""));
+        let argument = argument.trim();
+        match name {
+            "fragment" => match argument.parse() {
+                Ok(n @ 1..=FRAGMENT_COUNT) => Ok(Delivery::Fragment(n - 1)),
+                _ => Err(format!("`{}` is not a fragment number", argument)),
+            },
+            "solved" if !argument.is_empty() => Ok(Delivery::Solved(argument.to_string())),
+            "after" => parse_duration(argument)
+                .map(Delivery::After)
+                .ok_or_else(|| format!("`{}` is not a duration like `15m` or `90s`", argument)),
+            "manual" => Ok(Delivery::Manual),
+            "solved" => Err(format!("`{}` needs a tab name", name)),
+            _ => Err(format!("unknown delivery condition `{}`", name)),
+        }
+    }
+}
+
+/// Where the game is at, to know which emails have arrived.
+pub struct Progress<'a> {
+    pub usbs_plugged: &'a [bool; FRAGMENT_COUNT],
+    pub routes: &'a Routes,
+    pub solved: &'a HashSet<usize>,
+    pub elapsed: Duration,
+}
+
+impl Delivery {
+    pub fn is_due(&self, progress: &Progress) -> bool {
+        match self {
+            Delivery::Immediate => true,
+            Delivery::Fragment(fragment) => progress.usbs_plugged[*fragment],
+            Delivery::Solved(name) => progress.routes.tab(name).is_some_and(|tab| progress.solved.contains(&tab)),
+            Delivery::After(duration) => progress.elapsed >= *duration,
+            Delivery::Manual => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn conditions() {
+        assert_eq!("fragment 1".parse(), Ok(Delivery::Fragment(0)));
+        assert_eq!(format!(" fragment {} ", FRAGMENT_COUNT).parse(), Ok(Delivery::Fragment(FRAGMENT_COUNT - 1)));
+        assert_eq!("solved decrypt".parse(), Ok(Delivery::Solved("decrypt".to_string())));
+        assert_eq!("after 15m".parse(), Ok(Delivery::After(Duration::from_secs(15 * 60))));
+        assert_eq!("after 90s".parse(), Ok(Delivery::After(Duration::from_secs(90))));
+        assert_eq!("manual".parse(), Ok(Delivery::Manual));
+    }
+
+    #[test]
+    fn bad_conditions() {
+        assert_eq!("fragment 0".parse::<Delivery>(), Err("`0` is not a fragment number".to_string()));
+        assert_eq!(format!("fragment {}", FRAGMENT_COUNT + 1).parse::<Delivery>(), Err(format!("`{}` is not a fragment number", FRAGMENT_COUNT + 1)));
+        assert_eq!("fragment deux".parse::<Delivery>(), Err("`deux` is not a fragment number".to_string()));
+        assert_eq!("solved".parse::<Delivery>(), Err("`solved` needs a tab name".to_string()));
+        assert_eq!("after 1h".parse::<Delivery>(), Err("`1h` is not a duration like `15m` or `90s`".to_string()));
+        assert_eq!("after -5m".parse::<Delivery>(), Err("`-5m` is not a duration like `15m` or `90s`".to_string()));
+        // The unit is always written
+        assert_eq!("after 15".parse::<Delivery>(), Err("`15` is not a duration like `15m` or `90s`".to_string()));
+        assert_eq!("after".parse::<Delivery>(), Err("`` is not a duration like `15m` or `90s`".to_string()));
+        assert_eq!("tomorrow".parse::<Delivery>(), Err("unknown delivery condition `tomorrow`".to_string()));
+    }
+
+    #[test]
+    fn due() {
+        let mut usbs_plugged = [false; FRAGMENT_COUNT];
+        usbs_plugged[1] = true;
+        let routes = Routes::new(HashMap::from([("decrypt".to_string(), 1)]), vec![None, Some(0)], 0, None);
+        let solved = HashSet::from([1]);
+        let progress = Progress { usbs_plugged: &usbs_plugged, routes: &routes, solved: &solved, elapsed: Duration::from_secs(60) };
+
+        assert!(Delivery::Immediate.is_due(&progress));
+        assert!(Delivery::Fragment(1).is_due(&progress));
+        assert!(!Delivery::Fragment(0).is_due(&progress));
+        assert!(Delivery::Solved("decrypt".to_string()).is_due(&progress));
+        assert!(!Delivery::Solved("absent".to_string()).is_due(&progress));
+        assert!(Delivery::After(Duration::from_secs(60)).is_due(&progress));
+        assert!(!Delivery::After(Duration::from_secs(61)).is_due(&progress));
+        assert!(!Delivery::Manual.is_due(&progress));
+    }
+}
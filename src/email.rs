@@ -1,98 +1,148 @@
 use std::cmp::Reverse;
-use crate::model::Tab;
-use anyhow::{Context, Result};
+use crate::attachment::{Attachment, Viewer};
+use crate::compose::{ComposeForm, Draft, Edit, Rule, SentEmail};
+use crate::date::{DateFormat, EmailDate};
+use crate::delivery::{Delivery, Progress};
+use crate::markup::{self, Run};
+use crate::model::{Tab, Transition};
+use crate::scenario::FRAGMENT_COUNT;
+use crate::session_log::{self, Entry};
+use anyhow::{anyhow, bail, Context, Result};
 use ratatui::buffer::Buffer;
-use ratatui::crossterm::event::{Event, KeyCode, KeyEventKind};
+use ratatui::crossterm::event::{Event, KeyCode, KeyEventKind, MouseButton, MouseEvent, MouseEventKind};
 use ratatui::layout::Rect;
 use ratatui::prelude::Stylize;
 use ratatui::prelude::*;
 use ratatui::text::ToSpan;
-use ratatui::widgets::{Block, Borders, List, ListState, Paragraph, ScrollbarState, StatefulWidgetRef, Wrap};
-use std::fs::DirEntry;
+use ratatui::widgets::{Block, Borders, HighlightSpacing, List, ListState, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, StatefulWidgetRef, Wrap};
+use std::collections::{HashMap, HashSet};
+use std::iter::once;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use awedio::manager::Manager;
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
 #[derive(Debug, Clone)]
 struct Email {
-    // A list of paragraphs. Each paragraph is a list of owned spans.
-    body: Vec<Vec<(String, Style)>>,
+    /// The file name, without the extension. Replies refer to it in `In-Reply-To`.
+    id: String,
+    in_reply_to: Option<String>,
+    thread: Option<String>,
+    /// Whether the email was read before the game, from its `Read: yes|no` header. Those filed with
+    /// the sent ones are, unless told otherwise.
+    read: bool,
+    /// Where the email is filed before the players move it.
+    folder: String,
+    /// From the comma-separated `Attachments` header, relative to where the game is started.
+    attachments: Vec<Attachment>,
+    delivery: Delivery,
+    // A list of lines, each in runs of one style
+    body: Vec<Vec<Run>>,
     from: String,
     to: String,
     cc: String,
     subject: String,
-    date: String,
+    date: Option<EmailDate>,
 }
 
 impl Email {
-    pub fn new_from_file(path: &Path) -> Result<Self> {
-        let file = std::fs::read_to_string(path)?;
+    pub fn new_from_file(path: &Path, folder: &str) -> Result<Self> {
+        let file = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read {}", path.display()))?;
 
-        let mut lines = file.lines();
-        let from = lines.next().context("No `from` line")?.trim_start_matches("from:").trim().to_string();
-        let to = lines.next().context("No `to` line")?.trim_start_matches("to:").trim().to_string();
-        let cc = lines.next().context("No `cc` line")?.trim_start_matches("cc:").to_string();
-        let date = lines.next().context("No `date` line")?.trim_start_matches("date:").trim().to_string();
-        let subject = lines.next().context("No `subject` line")?.trim_start_matches("subject:").trim().to_string();
-
-        let mut body = vec![];
-
-        for line in lines.skip_while(|x| x.is_empty()) {
-            if line.is_empty() {
-                body.push(vec![]);
-                continue;
-            }
-
-            let mut current_paragraph = vec![];
-            let mut current_span = String::new();
-            let mut current_style = Style::new();
-
-            let mut cancel_next_delim = false;
-            for split in line.split_inclusive(&['*', '\\', '_', '~']) {
-                if split.len() == 1 && cancel_next_delim {
-                    current_span.push_str(split);
-                    cancel_next_delim = false;
+        let mut lines = file.lines().enumerate();
+        let mut from = None;
+        let mut to = None;
+        let mut cc = None;
+        let mut date = None;
+        let mut subject = None;
+        let mut in_reply_to = None;
+        let mut thread = None;
+        let mut read = None;
+        let mut attachments = None;
+        let mut delivery = None;
+        for header in parse_headers(path, &mut lines)? {
+            let field = match header.key.to_lowercase().as_str() {
+                "from" => &mut from,
+                "to" => &mut to,
+                "cc" => &mut cc,
+                "date" => {
+                    if date.is_some() {
+                        bail!("{}:{}: `{}` is given twice", path.display(), header.line, header.key);
+                    }
+                    date = Some(header.value.parse::<EmailDate>()
+                        .map_err(|e| anyhow!("{}:{}: {}", path.display(), header.line, e))?);
                     continue;
                 }
-
-                match split.chars().last().unwrap() {
-                    '*' => {
-                        current_span.push_str(&split[..split.len()-1]);
-                        current_paragraph.push((current_span, current_style.clone()));
-                        current_span = String::new();
-                        current_style.add_modifier.toggle(Modifier::BOLD);
-                    }
-                    '_' => {
-                        current_span.push_str(&split[..split.len()-1]);
-                        current_paragraph.push((current_span, current_style.clone()));
-                        current_span = String::new();
-                        current_style.add_modifier.toggle(Modifier::ITALIC);
-                    }
-                    '~' => {
-                        current_span.push_str(&split[..split.len()-1]);
-                        current_paragraph.push((current_span, current_style.clone()));
-                        current_span = String::new();
-                        match current_style.fg {
-                            None => {
-                                current_style.fg = Some(Color::Red)
-                            },
-                            Some(_) => {
-                                current_style.fg = None
-                            },
-                        }
+                "subject" => &mut subject,
+                "in-reply-to" => &mut in_reply_to,
+                "thread" => &mut thread,
+                "read" => {
+                    if read.is_some() {
+                        bail!("{}:{}: `{}` is given twice", path.display(), header.line, header.key);
                     }
-                    '\\' => {
-                        cancel_next_delim = true;
+                    read = Some(match header.value.to_lowercase().as_str() {
+                        "yes" => true,
+                        "no" => false,
+                        _ => bail!("{}:{}: `{}` should be `yes` or `no`", path.display(), header.line, header.key),
+                    });
+                    continue;
+                }
+                "deliver" => {
+                    if delivery.is_some() {
+                        bail!("{}:{}: `{}` is given twice", path.display(), header.line, header.key);
                     }
-                    _ => {
-                        current_span.push_str(split);
+                    delivery = Some(header.value.parse::<Delivery>()
+                        .map_err(|e| anyhow!("{}:{}: {}", path.display(), header.line, e))?);
+                    continue;
+                }
+                "attachments" => {
+                    if attachments.is_some() {
+                        bail!("{}:{}: `{}` is given twice", path.display(), header.line, header.key);
                     }
+                    let files = header.value.split(',').map(str::trim).filter(|x| !x.is_empty());
+                    attachments = Some(files
+                        .map(|file| Attachment::load(Path::new(file)))
+                        .collect::<Result<Vec<_>>>()
+                        .with_context(|| format!("{}:{}: an attachment could not be loaded", path.display(), header.line))?);
+                    continue;
+                }
+                _ => {
+                    session_log::record(Entry::Warning {
+                        message: format!("{}:{}: unknown header `{}`", path.display(), header.line, header.key),
+                    });
+                    continue;
                 }
+            };
+            if field.is_some() {
+                bail!("{}:{}: `{}` is given twice", path.display(), header.line, header.key);
             }
-
-            current_paragraph.push((current_span, current_style.clone()));
-            body.push(current_paragraph);
+            *field = Some(header.value);
+        }
+        let from = from.unwrap_or_default();
+        let to = to.unwrap_or_default();
+        let cc = cc.unwrap_or_default();
+        let subject = subject.unwrap_or_default();
+        let mut warnings = vec![];
+        let body = markup::parse(lines.skip_while(|(_, x)| x.is_empty()).map(|(index, line)| (index + 1, line)), &mut warnings)
+            .map_err(|e| anyhow!("{}:{}: {}", path.display(), e.line, e.message))?;
+        for warning in warnings {
+            session_log::record(Entry::Warning { message: format!("{}:{}: {}", path.display(), warning.line, warning.message) });
         }
 
         Ok(Self {
+            id: path.file_stem().unwrap_or_default().to_string_lossy().to_string(),
+            in_reply_to,
+            thread,
+            read: read.unwrap_or(folder == SENT),
+            folder: folder.to_string(),
+            attachments: attachments.unwrap_or_default(),
+            delivery: delivery.unwrap_or(Delivery::Immediate),
             body,
             from,
             to,
@@ -102,64 +152,1218 @@ impl Email {
         })
     }
 
-    pub fn get_stub(&self) -> Text {
-        Text::from(vec![
-            Span::styled(&self.from, Style::new()).into_left_aligned_line(),
-            Span::styled(&self.date, Modifier::BOLD).into_left_aligned_line(),
+    pub fn get_stub(&self, unread: bool, dates: &DateFormat, sent: bool) -> Text<'_> {
+        let stub = Text::from(vec![
+            self.correspondent(unread, sent),
+            Span::styled(self.date.map(|x| dates.show(&x)).unwrap_or_default(), Modifier::BOLD).into_left_aligned_line(),
             Span::styled(&self.subject, Style::new()).into_left_aligned_line(),
             Line::raw(""),
-        ])
+        ]);
+        if unread { stub.bold() } else { stub }
+    }
+
+    /// Who wrote the email, or who it was written to among the sent ones.
+    fn correspondent(&self, unread: bool, sent: bool) -> Line<'_> {
+        if sent {
+            Line::from(vec!["à: ".to_span().dim(), self.to.to_span()])
+        } else {
+            Line::from(vec![unread_marker(unread), self.from.to_span()])
+        }
+    }
+
+    /// An email the players wrote, to show it like the others.
+    fn written(email: &SentEmail) -> Self {
+        Self {
+            id: String::new(),
+            in_reply_to: None,
+            thread: None,
+            read: true,
+            folder: SENT.to_string(),
+            attachments: vec![],
+            delivery: Delivery::Immediate,
+            body: email.body.lines()
+                .map(|line| if line.is_empty() { vec![] } else { vec![Run { text: line.to_string(), style: Style::new(), fragment: None }] })
+                .collect(),
+            from: "moi".to_string(),
+            to: email.to.clone(),
+            cc: String::new(),
+            subject: email.subject.clone(),
+            date: None,
+        }
+    }
+
+    fn is_reply(&self) -> bool {
+        base_subject(&self.subject) != self.subject.trim()
     }
 }
 
-impl<'a> Into<Text<'a>> for &'a Email {
-    fn into(self) -> Text<'a> {
-        Text {
-            lines: self.body.iter().map(|line| Line::from_iter(line.iter().map(|span| {
-                Span::styled(&span.0, span.1)
-            }))).collect(),
-            style: Default::default(),
-            alignment: None,
+fn unread_marker(unread: bool) -> Span<'static> {
+    if unread {
+        Span::styled("● ", Color::Green)
+    } else {
+        Span::raw("")
+    }
+}
+
+/// The subject without its "RE:" and "TR:" prefixes, of answers and forwards.
+fn base_subject(subject: &str) -> &str {
+    let mut subject = subject.trim();
+    while let Some(prefix) = subject.get(..2).filter(|x| x.eq_ignore_ascii_case("re") || x.eq_ignore_ascii_case("tr")) {
+        match subject[prefix.len()..].trim_start().strip_prefix(':') {
+            Some(rest) => subject = rest.trim_start(),
+            None => break,
+        }
+    }
+    subject
+}
+
+struct Header {
+    key: String,
+    value: String,
+    // Where the header starts, counting from 1
+    line: usize,
+}
+
+/// Reads `key: value` headers up to the first empty line, which is consumed too. A line starting
+/// with a space or a tab continues the header above it.
+fn parse_headers<'a>(path: &Path, lines: &mut impl Iterator<Item=(usize, &'a str)>) -> Result<Vec<Header>> {
+    let mut headers: Vec<Header> = vec![];
+    for (index, line) in lines {
+        if line.trim().is_empty() {
+            break;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            let Some(header) = headers.last_mut() else {
+                bail!("{}:{}: the headers start with a continuation line", path.display(), index + 1);
+            };
+            if !header.value.is_empty() {
+                header.value.push(' ');
+            }
+            header.value.push_str(line.trim());
+            continue;
         }
+
+        let Some((key, value)) = line.split_once(':') else {
+            bail!("{}:{}: expected a `key: value` header", path.display(), index + 1);
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            bail!("{}:{}: `{}` is not a header name", path.display(), index + 1, key);
+        }
+        headers.push(Header {
+            key: key.to_string(),
+            value: value.trim().to_string(),
+            line: index + 1,
+        });
     }
+    Ok(headers)
 }
 
+/// How much of a corrupted portion the players can read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Legibility {
+    Corrupted,
+    /// Being decoded, from 0 to 1
+    Decoding(f32),
+    Legible,
+}
+
+const GLITCHES: &[char] = &['#', '%', '&', '@', '$', '?', '!', '/', '\\', '<', '>', '=', '░', '▒', '▓', '█'];
+
+/// Scrambles the numbers into something that looks random, but always gives the same result.
+fn noise(a: usize, b: usize) -> usize {
+    let mut x = (a as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (b as u64).wrapping_add(0x632B_E59B_D9B4_E019);
+    x ^= x >> 31;
+    x = x.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x ^= x >> 29;
+    x as usize
+}
+
+/// Replaces the letters that aren't decoded yet with glitches, keeping the spaces so that the text
+/// wraps the same once legible. `seed` tells the spans apart, `frame` makes the glitches move.
+fn glitch(text: &str, decoded: f32, seed: usize, frame: usize) -> String {
+    text.chars().enumerate().map(|(i, c)| {
+        let threshold = (noise(seed, i) % 1000) as f32 / 1000.0;
+        if c.is_whitespace() || decoded > threshold {
+            c
+        } else {
+            GLITCHES[noise(seed ^ i, frame) % GLITCHES.len()]
+        }
+    }).collect()
+}
+
+/// Lowercase and without its accent, so that "eve" finds "Ève". Each character stays one character,
+/// to find where the matches are in the original text.
+fn fold(c: char) -> char {
+    let base = once(c).nfd().find(|x| !is_combining_mark(*x)).unwrap_or(c);
+    match base {
+        '’' | '‘' => '\'',
+        _ => base.to_lowercase().next().unwrap_or(base),
+    }
+}
+
+pub fn fold_str(text: &str) -> Vec<char> {
+    text.chars().map(fold).collect()
+}
+
+/// Where `query` is in `text`, both folded, in characters.
+pub fn find_all(text: &[char], query: &[char]) -> Vec<Range<usize>> {
+    if query.is_empty() {
+        return vec![];
+    }
+    text.windows(query.len())
+        .enumerate()
+        .filter(|(_, window)| *window == query)
+        .map(|(i, _)| i..i + query.len())
+        .collect()
+}
+
+/// Shows where the search matched, keeping the styles of the text around.
+fn highlight<'a>(text: Text<'a>, query: &[char]) -> Text<'a> {
+    if query.is_empty() {
+        return text;
+    }
+    let lines = text.lines.into_iter().map(|line| {
+        let chars: Vec<char> = line.spans.iter().flat_map(|span| span.content.chars()).map(fold).collect();
+        let found = find_all(&chars, query);
+        if found.is_empty() {
+            return line;
+        }
+
+        // Spans are cut where matches start and end
+        let mut spans = vec![];
+        let mut position = 0;
+        for span in line.spans {
+            let mut piece = String::new();
+            let mut highlighted = false;
+            for c in span.content.chars() {
+                let here = found.iter().any(|x| x.contains(&position));
+                if here != highlighted && !piece.is_empty() {
+                    let style = if highlighted { span.style.patch(SEARCH_HIGHLIGHT) } else { span.style };
+                    spans.push(Span::styled(std::mem::take(&mut piece), style));
+                }
+                highlighted = here;
+                piece.push(c);
+                position += 1;
+            }
+            let style = if highlighted { span.style.patch(SEARCH_HIGHLIGHT) } else { span.style };
+            spans.push(Span::styled(piece, style));
+        }
+        Line { spans, ..line }
+    }).collect();
+    Text { lines, ..text }
+}
+
+const SEARCH_HIGHLIGHT: Style = Style::new().fg(Color::Black).bg(Color::Yellow);
+
+impl Email {
+    /// Whether the email mentions the folded `query`, in its addresses, its subject or the legible
+    /// part of its body.
+    fn matches(&self, query: &[char], legible: impl Fn(usize) -> bool) -> bool {
+        let found = |text: &str| !find_all(&fold_str(text), query).is_empty();
+        [&self.from, &self.to, &self.cc, &self.subject].into_iter().any(|x| found(x))
+            || self.body.iter().any(|line| {
+                found(&line.iter()
+                    .filter(|run| run.fragment.is_none_or(&legible))
+                    .map(|run| run.text.as_str())
+                    .collect::<String>())
+            })
+    }
+
+    /// The fragments whose corrupted portions are in the body.
+    fn portions(&self) -> HashSet<usize> {
+        self.body.iter().flatten().filter_map(|run| run.fragment).collect()
+    }
+
+    fn body_text(&self, legibility: impl Fn(usize) -> Legibility, frame: usize) -> Text<'_> {
+        Text::from_iter(self.body.iter().enumerate().map(|(n, line)| {
+            Line::from_iter(line.iter().enumerate().map(|(m, run)| {
+                let seed = noise(n, m);
+                match run.fragment.map(&legibility) {
+                    None | Some(Legibility::Legible) => Span::styled(&run.text, run.style),
+                    Some(Legibility::Corrupted) => Span::styled(glitch(&run.text, 0.0, seed, 0), run.style.fg(Color::Red)),
+                    Some(Legibility::Decoding(decoded)) => Span::styled(glitch(&run.text, decoded, seed, frame), run.style.fg(Color::Red)),
+                }
+            }))
+        }))
+    }
+}
+
+/// A conversation: its emails in reading order, each with how deep it is in the replies.
 #[derive(Debug, Clone)]
+struct Thread {
+    messages: Vec<(usize, usize)>,
+}
+
+impl Thread {
+    fn root(&self) -> usize {
+        self.messages[0].0
+    }
+}
+
+/// Groups the emails into conversations, the most recent first. A reply names the email it
+/// answers with `In-Reply-To`, a `Thread` header puts emails in the conversation of that name, and
+/// otherwise emails with the same subject, "RE:" and "TR:" aside, go together.
+fn build_threads(emails: &[Email]) -> Vec<Thread> {
+    let ids: HashMap<&str, usize> = emails.iter().enumerate().map(|(i, x)| (x.id.as_str(), i)).collect();
+    let parent = |i: usize| {
+        emails[i].in_reply_to.as_deref()
+            .and_then(|id| ids.get(id).copied())
+            .filter(|&x| x != i)
+    };
+
+    let mut groups: Vec<(String, Vec<usize>)> = vec![];
+    for (i, email) in emails.iter().enumerate() {
+        if let Some(id) = &email.in_reply_to {
+            if parent(i).is_none() {
+                session_log::record(Entry::Warning {
+                    message: format!("{}: `In-Reply-To` names `{}`, which is not an email", email.id, id),
+                });
+            }
+        }
+
+        // Follows the replies up to the conversation, giving up on loops
+        let mut current = i;
+        let mut key = None;
+        for _ in 0..emails.len() {
+            if let Some(thread) = &emails[current].thread {
+                key = Some(format!("thread:{}", thread));
+                break;
+            }
+            match parent(current) {
+                Some(x) => current = x,
+                None => break,
+            }
+        }
+        let key = key.unwrap_or_else(|| format!("subject:{}", base_subject(&emails[current].subject).to_lowercase()));
+
+        match groups.iter_mut().find(|(x, _)| *x == key) {
+            Some((_, members)) => members.push(i),
+            None => groups.push((key, vec![i])),
+        }
+    }
+
+    let mut threads: Vec<Thread> = groups.into_iter().map(|(_, mut members)| {
+        members.sort_by(|&a, &b| (emails[a].date, &emails[a].id).cmp(&(emails[b].date, &emails[b].id)));
+        let in_thread_parent = |i: usize| parent(i).filter(|x| members.contains(x));
+        // The first email that doesn't answer another one starts the conversation
+        let root = *members.iter()
+            .min_by_key(|&&i| (in_thread_parent(i).is_some(), emails[i].is_reply()))
+            .unwrap();
+        let parent_of = |i: usize| if i == root { None } else { Some(in_thread_parent(i).unwrap_or(root)) };
+
+        let mut messages = vec![];
+        let mut stack = vec![(root, 0)];
+        while let Some((i, depth)) = stack.pop() {
+            messages.push((i, depth));
+            // Pushed backwards, so that the earliest answer comes out first
+            for &child in members.iter().rev() {
+                if parent_of(child) == Some(i) && !messages.iter().any(|&(x, _)| x == child) {
+                    stack.push((child, depth + 1));
+                }
+            }
+        }
+        // Emails answering each other in a loop
+        for &i in &members {
+            if !messages.iter().any(|&(x, _)| x == i) {
+                messages.push((i, 1));
+            }
+        }
+        Thread { messages }
+    }).collect();
+
+    threads.sort_by_key(|thread| {
+        let latest = thread.messages.iter().map(|&(i, _)| emails[i].date).max().unwrap();
+        (Reverse(latest), emails[thread.root()].id.clone())
+    });
+    threads
+}
+
+/// The folders every mailbox has, in the order they are listed. The emails at the root of the
+/// emails folder are in the inbox, the others in the subdirectory named after their folder.
+const INBOX: &str = "inbox";
+const SENT: &str = "sent";
+const TRASH: &str = "trash";
+const STANDARD_FOLDERS: [&str; 4] = [INBOX, SENT, "archive", TRASH];
+
+fn folder_name(folder: &str) -> &str {
+    match folder {
+        INBOX => "Réception",
+        SENT => "Envoyés",
+        "archive" => "Archives",
+        TRASH => "Corbeille",
+        other => other,
+    }
+}
+
+/// The `.email` files of each folder, in the order of their names.
+fn mailbox(root: &Path) -> Result<Vec<(String, Vec<PathBuf>)>> {
+    let mut folders = vec![(INBOX.to_string(), root.to_path_buf())];
+    for entry in std::fs::read_dir(root).with_context(|| format!("could not read {}", root.display()))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        if entry.file_type()?.is_dir() && !name.starts_with('.') {
+            folders.push((name, entry.path()));
+        }
+    }
+    folders.sort();
+
+    let mut mailbox: Vec<(String, Vec<PathBuf>)> = vec![];
+    for (folder, path) in folders {
+        let mut paths = vec![];
+        for entry in std::fs::read_dir(&path).with_context(|| format!("could not read {}", path.display()))? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() && entry.path().extension() == Some(std::ffi::OsStr::new("email")) {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+        // `inbox/` is the root too
+        match mailbox.iter_mut().find(|(x, _)| *x == folder) {
+            Some((_, x)) => x.extend(paths),
+            None => mailbox.push((folder, paths)),
+        }
+    }
+    Ok(mailbox)
+}
+
+/// Every email of the mailbox, and its folders.
+fn load_mailbox(root: &Path) -> Result<(Vec<Email>, Vec<String>)> {
+    let mailbox = mailbox(root)?;
+    let mut emails: Vec<Email> = vec![];
+    for (folder, paths) in &mailbox {
+        for path in paths {
+            let email = Email::new_from_file(path, folder)?;
+            if emails.iter().any(|x| x.id == email.id) {
+                bail!("{}: another folder has an email named `{}`", path.display(), email.id);
+            }
+            emails.push(email);
+        }
+    }
+    Ok((emails, folder_list(&mailbox)))
+}
+
+/// An email as the players see it, to print it.
+pub struct Printed {
+    pub id: String,
+    pub subject: String,
+    /// The non-empty ones, labelled like on screen
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Text<'static>,
+}
+
+/// Every email of the mailbox, with its corrupted portions legible unless `corrupted`.
+pub fn printed(root: &Path, corrupted: bool, dates: &DateFormat) -> Result<Vec<Printed>> {
+    let (emails, _) = load_mailbox(root)?;
+    Ok(emails.iter().map(|email| {
+        let attachments = email.attachments.iter().map(|x| x.name.as_str()).collect::<Vec<_>>().join(", ");
+        let headers = [
+            ("de", email.from.clone()),
+            ("le", email.date.map(|x| dates.show(&x)).unwrap_or_default()),
+            ("à", email.to.clone()),
+            ("cc", email.cc.clone()),
+            ("sujet", email.subject.clone()),
+            ("pièces jointes", attachments),
+        ];
+        let legibility = |_| if corrupted { Legibility::Corrupted } else { Legibility::Legible };
+        let body = email.body_text(legibility, 0).lines.into_iter()
+            .map(|line| Line {
+                spans: line.spans.into_iter().map(|span| Span::styled(span.content.into_owned(), span.style)).collect(),
+                ..line
+            })
+            .collect();
+        Printed {
+            id: email.id.clone(),
+            subject: email.subject.clone(),
+            headers: headers.into_iter().filter(|(_, x)| !x.trim().is_empty()).collect(),
+            body,
+        }
+    }).collect())
+}
+
+/// The standard folders, then the others of the mailbox.
+fn folder_list(mailbox: &[(String, Vec<PathBuf>)]) -> Vec<String> {
+    let mut folders: Vec<String> = STANDARD_FOLDERS.iter().map(|x| x.to_string()).collect();
+    for (folder, _) in mailbox {
+        if !folders.contains(folder) {
+            folders.push(folder.clone());
+        }
+    }
+    folders
+}
+
+/// Editors save in several steps, so the folder is read again once it has been quiet for this long.
+const RELOAD_DELAY: Duration = Duration::from_millis(300);
+
+/// Tells when the files of the emails folder, or of its folders, change.
+#[derive(Debug)]
+struct FolderWatcher {
+    // Dropping the watcher would stop the events
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    // When the last change was seen, if the folder wasn't read since
+    last_change: Option<Instant>,
+}
+
+impl FolderWatcher {
+    fn new(folder: &Path) -> Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(folder, RecursiveMode::Recursive)
+            .with_context(|| format!("could not watch {}", folder.display()))?;
+        Ok(Self {
+            _watcher: watcher,
+            events: rx,
+            last_change: None,
+        })
+    }
+
+    /// Whether the folder changed, and then settled.
+    fn changed(&mut self) -> bool {
+        for event in self.events.try_iter().flatten() {
+            if !matches!(event.kind, EventKind::Access(_)) {
+                self.last_change = Some(Instant::now());
+            }
+        }
+        if self.last_change.is_some_and(|x| x.elapsed() >= RELOAD_DELAY) {
+            self.last_change = None;
+            return true;
+        }
+        false
+    }
+}
+
+/// A line of the email list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Row {
+    /// A conversation of several emails, read as a whole.
+    Thread(usize),
+    /// An email, indented by its depth in the conversation.
+    Email(usize, usize),
+    /// An email the players wrote, counting from the first one they sent.
+    Written(usize),
+}
+
+#[derive(Debug)]
 pub struct EmailProgram {
     emails: Vec<Email>,
+    threads: Vec<Thread>,
+    // As listed in the folder pane
+    folders: Vec<String>,
+    // What sending an email does
+    rules: Vec<Rule>,
+    dates: DateFormat,
+    folder: PathBuf,
+    watcher: Option<FolderWatcher>,
+    // Attachments are played through it
+    manager: Manager,
 }
 
 impl EmailProgram {
-    pub fn new(folder_path: PathBuf) -> Result<Self> {
-        let mut paths: Vec<_> = std::fs::read_dir(folder_path).unwrap()
-            .map(|entry| Ok(entry?))
-            .collect::<Result<Vec<DirEntry>>>()?;
+    pub fn new(folder_path: PathBuf, rules: Vec<Rule>, dates: DateFormat, manager: Manager) -> Result<Self> {
+        let (emails, folders) = load_mailbox(&folder_path)?;
+        let threads = build_threads(&emails);
 
-        paths.sort_by_key(|dir| dir.path());
+        // The game goes on without it, the emails just won't change
+        let watcher = FolderWatcher::new(&folder_path)
+            .inspect_err(|e| session_log::record(Entry::Warning {
+                message: format!("{:#}, the emails won't be reloaded when they change", e),
+            }))
+            .ok();
 
-        let mut emails = vec![];
-        for i in paths {
-            let file_type = i.file_type()?;
+        for (n, rule) in rules.iter().enumerate() {
+            let Some(reply) = &rule.reply else {
+                continue;
+            };
+            match emails.iter().find(|x| x.id == *reply) {
+                None => bail!("rule {}: there is no email `{}` to reply with", n + 1, reply),
+                Some(email) if email.delivery == Delivery::Immediate => {
+                    bail!("rule {}: `{}` replies to the players, but is there from the start; give it `deliver: manual`", n + 1, reply);
+                }
+                Some(_) => {}
+            }
+            if rule.solves && rule.goto.is_some() {
+                bail!("rule {}: an email can't both solve the tab and go to another one", n + 1);
+            }
+        }
+
+        Ok(EmailProgram { emails, threads, folders, rules, dates, folder: folder_path, watcher, manager })
+    }
+
+    /// Reads the folder again if the game master changed it. Emails that can't be read anymore are
+    /// logged, and stay as they were.
+    pub fn reload_if_changed(&mut self, state: &mut EmailProgramState) {
+        if !self.watcher.as_mut().is_some_and(FolderWatcher::changed) {
+            return;
+        }
 
-            if !file_type.is_file() {
+        let mailbox = match mailbox(&self.folder) {
+            Ok(mailbox) => mailbox,
+            Err(e) => {
+                session_log::record(Entry::Error { message: format!("{:#}", e) });
+                return;
+            }
+        };
+        let mut emails: Vec<Email> = vec![];
+        for (folder, paths) in &mailbox {
+            for path in paths {
+                let email = match Email::new_from_file(path, folder) {
+                    Ok(email) => Some(email),
+                    Err(e) => {
+                        session_log::record(Entry::Error { message: format!("{:#}", e) });
+                        let id = path.file_stem().unwrap_or_default().to_string_lossy();
+                        self.emails.iter().find(|x| x.id == id).cloned()
+                    }
+                };
+                match email {
+                    Some(email) if emails.iter().any(|x| x.id == email.id) => session_log::record(Entry::Error {
+                        message: format!("{}: another folder has an email named `{}`", path.display(), email.id),
+                    }),
+                    Some(email) => emails.push(email),
+                    None => {}
+                }
+            }
+        }
+
+        // Rows are positions in the list, so the selection is found again by id
+        let rows = self.rows(state);
+        let selected = state.list_state.selected().and_then(|x| rows.get(x)).copied();
+        let id = selected.and_then(|row| match row {
+            Row::Thread(t) => Some(self.thread_key(t).to_string()),
+            Row::Email(email, _) => Some(self.emails[email].id.clone()),
+            Row::Written(_) => None,
+        });
+
+        self.threads = build_threads(&emails);
+        self.emails = emails;
+        self.folders = folder_list(&mailbox);
+
+        let rows = self.rows(state);
+        let position = match (selected, id) {
+            (_, Some(id)) => rows.iter().position(|&row| match row {
+                Row::Thread(t) => self.thread_key(t) == id,
+                Row::Email(email, _) => self.emails[email].id == id,
+                Row::Written(_) => false,
+            })
+            .or_else(|| rows.iter().position(|&row| {
+                matches!(row, Row::Thread(t) if self.threads[t].messages.iter().any(|&(x, _)| self.emails[x].id == id))
+            })),
+            // The players' emails don't change
+            (Some(row), None) => rows.iter().position(|&x| x == row),
+            (None, None) => None,
+        };
+        state.list_state.select(position.or(Some(0)));
+        // The attachments may have changed too
+        state.attachment = 0;
+        state.viewing = false;
+        if state.focus == Focus::Attachments {
+            state.focus = Focus::List;
+        }
+    }
+
+    /// The tabs the rules show once an email is sent.
+    pub fn goto_tabs(&self) -> impl Iterator<Item=&str> {
+        self.rules.iter().filter_map(|rule| rule.goto.as_deref())
+    }
+
+    fn thread_key(&self, thread: usize) -> &str {
+        &self.emails[self.threads[thread].root()].id
+    }
+
+    fn is_delivered(&self, email: usize, state: &EmailProgramState) -> bool {
+        self.emails[email].delivery == Delivery::Immediate || state.delivered.contains(&self.emails[email].id)
+    }
+
+    fn folder_of<'a>(&'a self, email: usize, state: &'a EmailProgramState) -> &'a str {
+        state.moved.get(&self.emails[email].id).unwrap_or(&self.emails[email].folder)
+    }
+
+    /// The emails of the conversation that have arrived in the open folder, and that match the
+    /// search.
+    fn messages(&self, thread: usize, state: &EmailProgramState) -> Vec<(usize, usize)> {
+        let query = state.query();
+        self.threads[thread].messages.iter()
+            .copied()
+            .filter(|&(i, _)| self.is_delivered(i, state) && self.folder_of(i, state) == state.folder)
+            .filter(|&(i, _)| query.is_empty() || self.emails[i].matches(&query, |f| state.recovered.contains(&f)))
+            .collect()
+    }
+
+    /// What the list shows: conversations, with the emails of the expanded ones below them. The
+    /// emails the players wrote come first in the sent folder, the last one on top.
+    fn rows(&self, state: &EmailProgramState) -> Vec<Row> {
+        let mut threads: Vec<_> = (0..self.threads.len())
+            .map(|t| (t, self.messages(t, state)))
+            .filter(|(_, messages)| !messages.is_empty())
+            .collect();
+        // A conversation goes back up when an answer arrives
+        threads.sort_by_key(|(_, messages)| {
+            Reverse(messages.iter().map(|&(i, _)| self.emails[i].date).max())
+        });
+
+        let mut rows = vec![];
+        if state.folder == SENT {
+            let query = state.query();
+            rows.extend((0..state.sent.len()).rev()
+                .filter(|&n| query.is_empty() || Email::written(&state.sent[n]).matches(&query, |_| true))
+                .map(Row::Written));
+        }
+        for (t, messages) in threads {
+            if let [(email, _)] = messages[..] {
+                rows.push(Row::Email(email, 0));
                 continue;
             }
+            rows.push(Row::Thread(t));
+            if state.expanded.contains(self.thread_key(t)) {
+                rows.extend(messages.iter().map(|&(email, depth)| Row::Email(email, depth + 1)));
+            }
+        }
+        rows
+    }
+
+    /// Emails whose condition isn't met yet wait for one of these tabs to be solved.
+    pub fn awaited_tabs(&self) -> impl Iterator<Item=&str> {
+        self.emails.iter().filter_map(|email| match &email.delivery {
+            Delivery::Solved(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Puts the emails whose condition is now met in the inbox, and returns their subjects.
+    pub fn deliver(&self, state: &mut EmailProgramState, progress: &Progress) -> Vec<String> {
+        let replies = std::mem::take(&mut state.replies);
+        let due = (0..self.emails.len())
+            .filter(|&i| !self.is_delivered(i, state))
+            .filter(|&i| self.emails[i].delivery.is_due(progress) || replies.contains(&self.emails[i].id))
+            .collect();
+        self.receive(state, due)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.emails.iter().any(|email| email.id == id)
+    }
+
+    /// Puts the email in the inbox whatever its condition, for the game master.
+    pub fn deliver_now(&self, state: &mut EmailProgramState, id: &str) -> Vec<String> {
+        let email = (0..self.emails.len())
+            .filter(|&i| self.emails[i].id == id && !self.is_delivered(i, state))
+            .collect();
+        self.receive(state, email)
+    }
+
+    fn receive(&self, state: &mut EmailProgramState, emails: Vec<usize>) -> Vec<String> {
+        if emails.is_empty() {
+            return vec![];
+        }
+        self.keep_selection(state, |state| {
+            for &i in &emails {
+                state.delivered.insert(self.emails[i].id.clone());
+            }
+        });
+        emails.into_iter().map(|i| self.emails[i].subject.clone()).collect()
+    }
+
+    /// Changes what the list shows. The players keep reading what they were reading, even if it
+    /// moved.
+    fn keep_selection(&self, state: &mut EmailProgramState, change: impl FnOnce(&mut EmailProgramState)) {
+        let rows = self.rows(state);
+        let selected = state.list_state.selected().and_then(|x| rows.get(x).copied());
+
+        change(state);
+
+        match selected {
+            Some(row) => {
+                let rows = self.rows(state);
+                let position = rows.iter().position(|&x| x == row)
+                    .or_else(|| rows.iter().position(|&x| Some(x) == self.thread_of(row).map(Row::Thread)));
+                if position.is_some() {
+                    state.list_state.select(position);
+                }
+            }
+            // An empty list has no selection
+            None => state.list_state.select(Some(0)),
+        }
+    }
+
+    fn stub(&self, row: Row, state: &EmailProgramState) -> Text<'_> {
+        match row {
+            Row::Email(email, depth) => {
+                let indent = "  ".repeat(depth);
+                let stub = self.emails[email].get_stub(!self.is_read(email, state), &self.dates, state.folder == SENT);
+                Text::from_iter(stub.lines.into_iter().map(|line| {
+                    Line::from_iter(once(Span::raw(indent.clone())).chain(line.spans))
+                }))
+            }
+            Row::Thread(t) => {
+                let messages = self.messages(t, state);
+                let root = &self.emails[messages[0].0];
+                let latest = messages.iter()
+                    .map(|&(i, _)| &self.emails[i])
+                    .max_by_key(|x| x.date)
+                    .unwrap();
+                let marker = if state.expanded.contains(self.thread_key(t)) { "▾" } else { "▸" };
+                let unread = messages.iter().any(|&(i, _)| !self.is_read(i, state));
+                let stub = Text::from(vec![
+                    latest.correspondent(unread, state.folder == SENT),
+                    Span::styled(latest.date.map(|x| self.dates.show(&x)).unwrap_or_default(), Modifier::BOLD).into_left_aligned_line(),
+                    Line::from(vec![
+                        Span::raw(format!("{} ({}) ", marker, messages.len())),
+                        Span::raw(base_subject(&root.subject)),
+                    ]),
+                    Line::raw(""),
+                ]);
+                if unread { stub.bold() } else { stub }
+            }
+            Row::Written(n) => Text::from(vec![
+                Line::from(vec!["à: ".to_span().dim(), Span::raw(state.sent[n].to.clone())]),
+                Line::raw(state.sent[n].subject.clone()),
+                Line::raw(""),
+            ]),
+        }
+    }
+
+    /// Every email of the conversation, one after the other.
+    fn thread_text(&self, thread: usize, state: &EmailProgramState) -> Text<'_> {
+        let mut lines = vec![];
+        for (n, (i, _)) in self.messages(thread, state).into_iter().enumerate() {
+            let email = &self.emails[i];
+            if n > 0 {
+                lines.push(Line::raw(""));
+            }
+            let mut header = vec!["── de: ".to_span().dim().bold(), email.from.to_span()];
+            if let Some(date) = &email.date {
+                header.extend([" le ".to_span().dim().bold(), self.dates.show(date).into()]);
+            }
+            header.push(" ──".to_span().dim().bold());
+            lines.push(Line::from(header));
+            lines.extend(self.body_text(i, state).lines);
+        }
+        Text::from(lines)
+    }
+
+    /// Corrupted portions become legible as their fragment is installed.
+    pub fn recover(&self, state: &mut EmailProgramState, progress: &Progress) {
+        state.recovered = (0..FRAGMENT_COUNT).filter(|&f| progress.usbs_plugged[f]).collect();
+    }
 
-            if i.path().extension() == Some(std::ffi::OsStr::new("email")) {
-                let email = Email::new_from_file(&i.path())?;
-                emails.push(email);
+    /// Starts decoding the portions of the email that were recovered since the players last read
+    /// it, and finishes it when the animation is over.
+    fn decode(&self, email: usize, state: &mut EmailProgramState) {
+        let id = &self.emails[email].id;
+        let decoded = state.decoded.get(id);
+        let pending = self.emails[email].portions().into_iter()
+            .filter(|f| state.recovered.contains(f) && !decoded.is_some_and(|x| x.contains(f)))
+            .collect::<Vec<_>>();
+        if pending.is_empty() {
+            return;
+        }
+        let start = *state.decoding.entry(id.clone()).or_insert_with(Instant::now);
+        if start.elapsed() >= DECODING_DURATION {
+            state.decoding.remove(id);
+            state.decoded.entry(id.clone()).or_default().extend(pending);
+        }
+    }
+
+    fn body_text(&self, email: usize, state: &EmailProgramState) -> Text<'_> {
+        let email = &self.emails[email];
+        let decoded = state.decoded.get(&email.id);
+        let decoding = state.decoding.get(&email.id).map(|x| x.elapsed());
+        let legibility = |fragment| {
+            if decoded.is_some_and(|x| x.contains(&fragment)) {
+                Legibility::Legible
+            } else if let (true, Some(elapsed)) = (state.recovered.contains(&fragment), decoding) {
+                Legibility::Decoding(elapsed.as_secs_f32() / DECODING_DURATION.as_secs_f32())
+            } else {
+                Legibility::Corrupted
             }
+        };
+        let frame = decoding.map(|x| x.as_millis() as usize / GLITCH_FRAME_MS).unwrap_or(0);
+        email.body_text(legibility, frame)
+    }
+
+    /// Marks what the players selected or opened as read. The email selected for them, when the
+    /// game starts or a folder opens, isn't until they do.
+    fn mark_read(&self, state: &mut EmailProgramState) {
+        let rows = self.rows(state);
+        let Some(&row) = state.list_state.selected().and_then(|x| rows.get(x)) else {
+            return;
+        };
+        for email in self.shown(row, state) {
+            state.read.insert(self.emails[email].id.clone());
         }
-        emails.sort_by_key(|email| Reverse(email.date.clone()));
+    }
 
-        Ok(EmailProgram { emails })
+    fn is_read(&self, email: usize, state: &EmailProgramState) -> bool {
+        self.emails[email].read || state.read.contains(&self.emails[email].id)
+    }
+
+    fn unread_count(&self, folder: &str, state: &EmailProgramState) -> usize {
+        (0..self.emails.len())
+            .filter(|&i| self.is_delivered(i, state) && !self.is_read(i, state) && self.folder_of(i, state) == folder)
+            .count()
+    }
+
+    /// The emails the row shows in the body pane.
+    fn shown(&self, row: Row, state: &EmailProgramState) -> Vec<usize> {
+        match row {
+            Row::Email(email, _) => vec![email],
+            Row::Thread(t) => self.messages(t, state).into_iter().map(|(i, _)| i).collect(),
+            Row::Written(_) => vec![],
+        }
+    }
+
+    fn attachments(&self, state: &EmailProgramState) -> Vec<&Attachment> {
+        let rows = self.rows(state);
+        let Some(&row) = state.list_state.selected().and_then(|x| rows.get(x)) else {
+            return vec![];
+        };
+        self.shown(row, state).into_iter().flat_map(|i| &self.emails[i].attachments).collect()
+    }
+
+    /// The conversation the row belongs to, if it's one of the folder's.
+    fn thread_of(&self, row: Row) -> Option<usize> {
+        match row {
+            Row::Thread(t) => Some(t),
+            Row::Email(email, _) => self.threads.iter()
+                .position(|thread| thread.messages.iter().any(|&(x, _)| x == email)),
+            Row::Written(_) => None,
+        }
+    }
+
+    /// Shows another folder, from its first email.
+    fn open_folder(&self, state: &mut EmailProgramState, folder: usize) {
+        state.folder = self.folders[folder].clone();
+        state.list_state.select(Some(0));
+        state.scroll = 0;
+        state.attachment = 0;
+    }
+
+    /// Files the selected emails in another folder. Those the players wrote stay with the sent ones.
+    fn move_to(&self, state: &mut EmailProgramState, folder: usize) {
+        let folder = &self.folders[folder];
+        let rows = self.rows(state);
+        let Some(position) = state.list_state.selected().filter(|&x| x < rows.len()) else {
+            return;
+        };
+        let emails = self.shown(rows[position], state);
+        if emails.is_empty() || *folder == state.folder {
+            return;
+        }
+        for &i in &emails {
+            let email = &self.emails[i];
+            if email.folder == *folder {
+                state.moved.remove(&email.id);
+            } else {
+                state.moved.insert(email.id.clone(), folder.clone());
+            }
+        }
+        session_log::record(Entry::Moved {
+            subject: self.emails[emails[0]].subject.clone(),
+            folder: folder.clone(),
+        });
+
+        // The next email takes its place
+        let n_rows = self.rows(state).len();
+        state.list_state.select(Some(position.min(n_rows.saturating_sub(1))));
+        state.scroll = 0;
+        state.attachment = 0;
+        if state.focus == Focus::Attachments {
+            state.focus = Focus::List;
+        }
+    }
+
+    fn handle_viewer_input(&self, code: KeyCode, state: &mut EmailProgramState) {
+        let page = state.body_height.saturating_sub(1).max(1);
+        match code {
+            KeyCode::Esc => state.viewing = false,
+            KeyCode::Char(' ') => {
+                if let Some(attachment) = self.attachments(state).get(state.attachment) {
+                    attachment.open(&self.manager);
+                }
+            }
+            KeyCode::Down => state.viewer_scroll = state.viewer_scroll.saturating_add(1),
+            KeyCode::Up => state.viewer_scroll = state.viewer_scroll.saturating_sub(1),
+            KeyCode::PageDown => state.viewer_scroll = state.viewer_scroll.saturating_add(page),
+            KeyCode::PageUp => state.viewer_scroll = state.viewer_scroll.saturating_sub(page),
+            KeyCode::Home => state.viewer_scroll = 0,
+            _ => {}
+        }
+    }
+
+    /// Files the email with the sent ones, and applies the first rule it matches.
+    fn send(&self, state: &mut EmailProgramState, email: SentEmail) -> Option<Transition> {
+        let rule = self.rules.iter().position(|x| x.matches(&email));
+        session_log::record(Entry::Sent {
+            to: email.to.clone(),
+            subject: email.subject.clone(),
+            body: email.body.clone(),
+            rule: rule.map(|x| x + 1),
+        });
+
+        // The players see it went out
+        state.sent.push(email);
+        state.search.clear();
+        state.searching = false;
+        state.folder = SENT.to_string();
+        state.list_state.select(Some(0));
+        state.focus = Focus::List;
+        state.scroll = 0;
+
+        let rule = &self.rules[rule?];
+        if let Some(reply) = &rule.reply {
+            // Arrives with the other deliveries, so that the players are told
+            state.replies.push(reply.clone());
+        }
+        if rule.solves {
+            Some(Transition::Solved)
+        } else {
+            rule.goto.clone().map(Transition::Goto)
+        }
+    }
+
+    /// Clicks select what's under the pointer, and the wheel scrolls it.
+    fn handle_mouse(&self, mouse: MouseEvent, state: &mut EmailProgramState) {
+        let position = Position::new(mouse.column, mouse.row);
+        let areas = state.areas.clone();
+        match mouse.kind {
+            MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
+                let down = mouse.kind == MouseEventKind::ScrollDown;
+                let scroll = |x: u16| if down { x.saturating_add(SCROLL_LINES) } else { x.saturating_sub(SCROLL_LINES) };
+                if state.viewing {
+                    state.viewer_scroll = scroll(state.viewer_scroll);
+                } else if areas.list.contains(position) {
+                    self.handle_list_input(if down { KeyCode::Down } else { KeyCode::Up }, state);
+                } else if areas.body.contains(position) {
+                    state.scroll = scroll(state.scroll);
+                }
+            }
+            MouseEventKind::Down(MouseButton::Left) if !state.viewing => {
+                if areas.folders.contains(position) {
+                    let folder = usize::from(position.y - areas.folders.y).min(self.folders.len() - 1);
+                    match state.moving.take() {
+                        Some(_) => self.move_to(state, folder),
+                        None => {
+                            self.open_folder(state, folder);
+                            state.focus = Focus::Folders;
+                        }
+                    }
+                    return;
+                }
+                state.moving = None;
+                if let Some(attachment) = areas.attachments.iter().position(|x| x.contains(position)) {
+                    state.focus = Focus::Attachments;
+                    state.attachment = attachment;
+                    self.attachments(state)[attachment].open(&self.manager);
+                    state.viewing = true;
+                    state.viewer_scroll = 0;
+                } else if areas.list.contains(position) {
+                    state.focus = Focus::List;
+                    let rows = self.rows(state);
+                    let mut y = areas.list.y;
+                    for (i, &row) in rows.iter().enumerate().skip(state.list_state.offset()) {
+                        y += self.stub(row, state).height() as u16;
+                        if position.y < y {
+                            // Clicking the open conversation again shows or hides its emails
+                            if state.list_state.selected() == Some(i) {
+                                self.handle_list_input(KeyCode::Enter, state);
+                            }
+                            state.list_state.select(Some(i));
+                            break;
+                        }
+                    }
+                } else if areas.body.contains(position) {
+                    state.focus = Focus::Body;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Choosing where the selected emails go, once `M` is pressed.
+    fn handle_move_input(&self, code: KeyCode, target: usize, state: &mut EmailProgramState) {
+        let n_folders = self.folders.len();
+        match code {
+            KeyCode::Down => state.moving = Some((target + 1) % n_folders),
+            KeyCode::Up => state.moving = Some((target + n_folders - 1) % n_folders),
+            KeyCode::Enter => {
+                state.moving = None;
+                self.move_to(state, target);
+            }
+            KeyCode::Esc => state.moving = None,
+            _ => {}
+        }
+    }
+
+    /// Typing in the search prompt, which `/` opens. Returns whether the key was for it.
+    fn handle_search_input(&self, code: KeyCode, state: &mut EmailProgramState) -> bool {
+        let before = state.query();
+        match code {
+            KeyCode::Char('/') if !state.searching => {
+                state.searching = true;
+                state.search.clear();
+            }
+            KeyCode::Esc if state.searching || !state.search.is_empty() => {
+                // The email found stays selected once the others are back
+                self.keep_selection(state, |state| {
+                    state.searching = false;
+                    state.search.clear();
+                });
+                return true;
+            }
+            KeyCode::Char(c) if state.searching => state.search.push(c),
+            KeyCode::Backspace if state.searching => {
+                state.search.pop();
+            }
+            KeyCode::Enter if state.searching => state.searching = false,
+            _ => return false,
+        }
+        // The first result is selected as the search changes
+        if state.query() != before {
+            state.list_state.select(Some(0));
+            state.scroll = 0;
+            state.attachment = 0;
+        }
+        true
+    }
+
+    fn handle_list_input(&self, code: KeyCode, state: &mut EmailProgramState) {
+        let rows = self.rows(state);
+        let current = state.list_state.selected().and_then(|x| rows.get(x).copied());
+        let selected = state.list_state.selected_mut();
+        let n_rows = rows.len();
+        if n_rows == 0 {
+            return;
+        }
+        match code {
+            KeyCode::Down => {
+                *selected = Some(selected.map(|x| (x+1) % n_rows).unwrap_or(0));
+            },
+            KeyCode::Up => {
+                *selected = Some(selected.map(|x| (x+n_rows - 1) % n_rows).unwrap_or(n_rows-1));
+            },
+            KeyCode::Right | KeyCode::Enter => {
+                if let Some(Row::Thread(t)) = current {
+                    let key = self.thread_key(t).to_string();
+                    if code == KeyCode::Enter && state.expanded.contains(&key) {
+                        state.expanded.remove(&key);
+                    } else {
+                        state.expanded.insert(key);
+                    }
+                }
+            },
+            KeyCode::Left => {
+                if let Some(t) = current.and_then(|row| self.thread_of(row)) {
+                    // Collapsing from one of its emails goes back up to the conversation
+                    state.expanded.remove(self.thread_key(t));
+                    if let Some(position) = self.rows(state).iter().position(|&x| x == Row::Thread(t)) {
+                        state.list_state.select(Some(position));
+                    }
+                }
+            },
+            _ => {}
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+/// How long corrupted portions take to decode once their fragment is installed.
+pub const DECODING_DURATION: Duration = Duration::from_secs(3);
+/// How often the glitches change while decoding.
+const GLITCH_FRAME_MS: usize = 80;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailProgramState {
     list_state: ListState,
     scrollbar_state: ScrollbarState,
+    // The conversations showing their emails, by the id of their first email
+    #[serde(default)]
+    expanded: HashSet<String>,
+    // The emails opened during the game
+    #[serde(default)]
+    read: HashSet<String>,
+    #[serde(default)]
+    focus: Focus,
+    // How many lines of the body are scrolled past
+    #[serde(default)]
+    scroll: u16,
+    // Known once the body is rendered, for paging
+    #[serde(skip)]
+    body_height: u16,
+    // Where things were last drawn, for the mouse
+    #[serde(skip)]
+    areas: Areas,
+    // Among those of the shown emails
+    #[serde(default)]
+    attachment: usize,
+    #[serde(default)]
+    viewing: bool,
+    #[serde(default)]
+    viewer_scroll: u16,
+    // The emails that arrived during the game
+    #[serde(default)]
+    delivered: HashSet<String>,
+    // The fragments installed, whose corrupted portions can be decoded
+    #[serde(default)]
+    recovered: HashSet<usize>,
+    // The portions the players saw decoded, by email
+    #[serde(default)]
+    decoded: HashMap<String, HashSet<usize>>,
+    // When the players started watching an email decode
+    #[serde(skip)]
+    decoding: HashMap<String, Instant>,
+    // Only the emails mentioning it are listed
+    #[serde(default)]
+    search: String,
+    // Whether the keys go to the search prompt
+    #[serde(default)]
+    searching: bool,
+    // The name of the open folder
+    #[serde(default = "inbox")]
+    folder: String,
+    // The folder the emails were moved to, by id, if not theirs
+    #[serde(default)]
+    moved: HashMap<String, String>,
+    // Where the selected emails would be moved, while the players choose
+    #[serde(default)]
+    moving: Option<usize>,
+    // The email being written, if any
+    #[serde(default)]
+    draft: Option<Draft>,
+    // In the order they were sent
+    #[serde(default)]
+    sent: Vec<SentEmail>,
+    // The emails answering those sent, delivered on the next update
+    #[serde(default)]
+    replies: Vec<String>,
+}
+
+/// Where the parts of the client were drawn, empty when hidden.
+#[derive(Debug, Clone, Default)]
+struct Areas {
+    // Inside the borders, one line per folder
+    folders: Rect,
+    // Inside the borders
+    list: Rect,
+    body: Rect,
+    // The name of each attachment
+    attachments: Vec<Rect>,
+}
+
+/// How many lines a turn of the mouse wheel scrolls.
+const SCROLL_LINES: u16 = 3;
+
+fn inbox() -> String {
+    INBOX.to_string()
+}
+
+/// Where the arrow keys go.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum Focus {
+    Folders,
+    #[default]
+    List,
+    Body,
+    Attachments,
 }
 
 impl EmailProgramState {
@@ -167,29 +1371,158 @@ impl EmailProgramState {
         Self {
             list_state: ListState::default().with_selected(Some(0)),
             scrollbar_state: ScrollbarState::default(),
+            expanded: HashSet::new(),
+            read: HashSet::new(),
+            focus: Focus::List,
+            scroll: 0,
+            body_height: 0,
+            areas: Areas::default(),
+            attachment: 0,
+            viewing: false,
+            viewer_scroll: 0,
+            delivered: HashSet::new(),
+            recovered: HashSet::new(),
+            decoded: HashMap::new(),
+            decoding: HashMap::new(),
+            search: String::new(),
+            searching: false,
+            folder: inbox(),
+            moved: HashMap::new(),
+            moving: None,
+            draft: None,
+            sent: vec![],
+            replies: vec![],
         }
     }
+
+    /// The search, folded.
+    fn query(&self) -> Vec<char> {
+        fold_str(self.search.trim())
+    }
 }
 
 impl Tab for EmailProgram {
-    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<usize> {
+    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<Transition> {
         match event {
-            Event::Key(event) => {
-                if event.kind == KeyEventKind::Press {
-                    let selected = state.list_state.selected_mut();
-                    let n_emails = self.emails.len();
-                    match event.code {
-                        KeyCode::Down => {
-                            *selected = Some(selected.map(|x| (x+1) % n_emails).unwrap_or(0));
-                        },
-                        KeyCode::Up => {
-                            *selected = Some(selected.map(|x| (x+n_emails - 1) % n_emails).unwrap_or(n_emails-1));
-                        },
-                        _ => {}
+            Event::Key(event) if event.kind == KeyEventKind::Press => {
+                if state.viewing {
+                    self.handle_viewer_input(event.code, state);
+                    return None;
+                }
+                if let Some(draft) = &mut state.draft {
+                    match draft.handle_input(event) {
+                        Edit::Writing => {}
+                        Edit::Cancel => state.draft = None,
+                        Edit::Send => {
+                            let email = state.draft.take().unwrap().email;
+                            return self.send(state, email);
+                        }
+                    }
+                    return None;
+                }
+                if let Some(target) = state.moving {
+                    self.handle_move_input(event.code, target, state);
+                    return None;
+                }
+                if self.handle_search_input(event.code, state) {
+                    return None;
+                }
+                let folder = self.folders.iter().position(|x| *x == state.folder).unwrap_or(0);
+                match (event.code, state.focus) {
+                    (KeyCode::Char('n' | 'N'), _) => {
+                        state.draft = Some(Draft::default());
+                        return None;
+                    }
+                    (KeyCode::Char('m' | 'M'), _) => {
+                        // The players' own emails can't be moved
+                        let rows = self.rows(state);
+                        let selected = state.list_state.selected().and_then(|x| rows.get(x));
+                        if selected.is_some_and(|&row| !self.shown(row, state).is_empty()) {
+                            state.moving = Some(folder);
+                        }
+                        return None;
+                    }
+                    (KeyCode::Delete, _) => {
+                        if let Some(trash) = self.folders.iter().position(|x| x == TRASH) {
+                            self.move_to(state, trash);
+                        }
+                        return None;
+                    }
+                    (KeyCode::Down, Focus::Folders) => {
+                        self.open_folder(state, (folder + 1) % self.folders.len());
+                        return None;
+                    }
+                    (KeyCode::Up, Focus::Folders) => {
+                        self.open_folder(state, (folder + self.folders.len() - 1) % self.folders.len());
+                        return None;
+                    }
+                    (KeyCode::Tab | KeyCode::Enter, Focus::Folders) => {
+                        state.focus = Focus::List;
+                        return None;
+                    }
+                    _ => {}
+                }
+
+                let before = state.list_state.selected();
+                let page = state.body_height.saturating_sub(1).max(1);
+                let attachments = self.attachments(state);
+                match (event.code, state.focus) {
+                    (KeyCode::Tab, Focus::List) => state.focus = Focus::Body,
+                    (KeyCode::Tab, Focus::Body) if !attachments.is_empty() => state.focus = Focus::Attachments,
+                    (KeyCode::Tab, _) => state.focus = Focus::Folders,
+                    (KeyCode::Right, Focus::Attachments) => {
+                        state.attachment = (state.attachment + 1) % attachments.len();
+                    }
+                    (KeyCode::Left, Focus::Attachments) => {
+                        state.attachment = (state.attachment + attachments.len() - 1) % attachments.len();
+                    }
+                    (KeyCode::Enter, Focus::Attachments) => {
+                        attachments[state.attachment].open(&self.manager);
+                        state.viewing = true;
+                        state.viewer_scroll = 0;
+                    }
+                    (KeyCode::PageDown, _) => state.scroll = state.scroll.saturating_add(page),
+                    (KeyCode::PageUp, _) => state.scroll = state.scroll.saturating_sub(page),
+                    (KeyCode::Home, _) => state.scroll = 0,
+                    // Brought back to the last line when rendering
+                    (KeyCode::End, _) => state.scroll = u16::MAX,
+                    (KeyCode::Down, Focus::Body) => state.scroll = state.scroll.saturating_add(1),
+                    (KeyCode::Up, Focus::Body) => state.scroll = state.scroll.saturating_sub(1),
+                    _ => self.handle_list_input(event.code, state),
+                }
+                let moved = state.list_state.selected() != before;
+                if moved {
+                    state.scroll = 0;
+                    state.attachment = 0;
+                    if state.focus == Focus::Attachments {
+                        state.focus = Focus::List;
                     }
                 }
+                if moved || matches!(event.code, KeyCode::Enter | KeyCode::Right) || matches!(state.focus, Focus::Body | Focus::Attachments) {
+                    self.mark_read(state);
+                }
+            }
+            Event::Mouse(mouse) if state.draft.is_none() => {
+                let before = state.list_state.selected();
+                let position = Position::new(mouse.column, mouse.row);
+                // Clicking in the list or scrolling the body opens the email, like pressing a key
+                let opened = !state.viewing && (state.areas.body.contains(position)
+                    || (mouse.kind == MouseEventKind::Down(MouseButton::Left) && state.areas.list.contains(position)));
+                // Opening or moving to a folder selects an email for the players
+                let on_folders = state.areas.folders.contains(position);
+                self.handle_mouse(mouse, state);
+                let moved = state.list_state.selected() != before;
+                if moved {
+                    state.scroll = 0;
+                    state.attachment = 0;
+                    if state.focus == Focus::Attachments {
+                        state.focus = Focus::List;
+                    }
+                }
+                if (moved && !on_folders) || opened {
+                    self.mark_read(state);
+                }
             }
-            Event::Mouse(_) => {}
             _ => {}
         }
         None
@@ -200,25 +1533,30 @@ impl StatefulWidgetRef for EmailProgram {
     type State = EmailProgramState;
 
     fn render_ref(&self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
-        fn email_list<'a>(list: &'a [Email], block: Block<'a>) -> impl StatefulWidget<State=ListState> + 'a {
+        fn email_list<'a>(program: &'a EmailProgram, rows: &[Row], state: &EmailProgramState, block: Block<'a>) -> impl StatefulWidget<State=ListState> + 'a {
             List::new(
-                list.iter()
-                    .enumerate()
-                    .map(|(i, x)| {
-                        x.get_stub()
+                rows.iter()
+                    .map(|&row| {
+                        program.stub(row, state)
                     })
+                    .collect::<Vec<_>>()
             )
                 .highlight_style(Style::from((Color::Green, Modifier::BOLD)))
                 .block(block)
         }
 
-        fn render_body(area: Rect, buf: &mut Buffer, email: &Email) {
-            let [from_date, to, cc, subject, body] = Layout::vertical([
+        const KEYS: &str = "<N> écrire  <M> déplacer";
+
+        // The headers are those of `email`, which starts the conversation when showing a whole one
+        #[allow(clippy::too_many_arguments)]
+        fn render_body(area: Rect, buf: &mut Buffer, email: &Email, dates: &DateFormat, text: Text, title: String, attachments: &[&Attachment], state: &mut EmailProgramState) {
+            let [from_date, to, cc, subject, body, attachment_area] = Layout::vertical([
                 Constraint::Length(2), // from: + date:
                 Constraint::Length(2), // to:
                 Constraint::Length(2), // cc:
                 Constraint::Length(2), // subject:
                 Constraint::Fill(1), // body:
+                Constraint::Length(if attachments.is_empty() { 0 } else { 3 }), // attachments:
             ]).areas(area);
 
             let border_set_top = symbols::border::Set {
@@ -237,15 +1575,15 @@ impl StatefulWidgetRef for EmailProgram {
                 ..symbols::border::Set::default()
             };
 
-            let top_par = Paragraph::new(Line::from(vec![
-                "de: ".to_span().dim().bold(),
-                email.from.to_span(),
-                " le ".to_span().dim().bold(),
-                email.date.to_span(),
-            ])).block(Block::new()
+            let mut from_line = vec!["de: ".to_span().dim().bold(), email.from.to_span()];
+            if let Some(date) = &email.date {
+                from_line.extend([" le ".to_span().dim().bold(), dates.show(date).into()]);
+            }
+            let top_par = Paragraph::new(Line::from(from_line)).block(Block::new()
                 .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT)
                 .border_set(border_set_top)
-                .title("Courriel actuel")
+                .title(title)
+                .title(Line::from(KEYS).right_aligned())
             );
 
             let mid_block = Block::new()
@@ -265,43 +1603,427 @@ impl StatefulWidgetRef for EmailProgram {
                 email.subject.to_span(),
             ])).block(mid_block.clone());
             
-            let body_par = Paragraph::new(email).wrap(Wrap { trim: true }).block(Block::new().borders(Borders::all()).border_set(border_set_bottom));
+            let body_block = if attachments.is_empty() {
+                Block::new().borders(Borders::all()).border_set(border_set_bottom)
+            } else {
+                mid_block.clone()
+            };
+            let body_block = if state.focus == Focus::Body { body_block.green() } else { body_block };
+            let body_inner = body_block.inner(body);
+            state.areas.body = body;
+            // Indentation is kept, for bullets and preformatted blocks
+            let body_par = Paragraph::new(text).wrap(Wrap { trim: false });
+            let max_scroll = body_par.line_count(body_inner.width).saturating_sub(body_inner.height as usize);
+            state.scroll = state.scroll.min(max_scroll.try_into().unwrap_or(u16::MAX));
+            state.body_height = body_inner.height;
+            state.scrollbar_state = ScrollbarState::new(max_scroll)
+                .viewport_content_length(body_inner.height as usize)
+                .position(state.scroll as usize);
+            let body_par = body_par.scroll((state.scroll, 0)).block(body_block);
             
             top_par.render(from_date, buf);
             to_par.render(to, buf);
             cc_par.render(cc, buf);
             subject_par.render(subject, buf);
             body_par.render(body, buf);
+            if max_scroll > 0 {
+                Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                    .render(body.inner(Margin::new(0, 1)), buf, &mut state.scrollbar_state);
+            }
+
+            if !attachments.is_empty() {
+                let label = "pièces jointes: ".to_span().dim().bold();
+                let names: Vec<_> = attachments.iter().enumerate().map(|(i, attachment)| {
+                    let name = Span::raw(format!("[{}]", attachment.name));
+                    if state.focus == Focus::Attachments && i == state.attachment { name.reversed() } else { name }
+                }).collect();
+                let block = Block::new().borders(Borders::all()).border_set(border_set_bottom);
+                let block = if state.focus == Focus::Attachments { block.green() } else { block };
+
+                let inner = block.inner(attachment_area);
+                let mut x = inner.x + label.width() as u16;
+                for name in &names {
+                    let width = name.width() as u16;
+                    state.areas.attachments.push(Rect::new(x, inner.y, width, 1).intersection(inner));
+                    x = x.saturating_add(width + 1);
+                }
+                let names = names.into_iter().flat_map(|name| [name, Span::raw(" ")]);
+                Paragraph::new(Line::from_iter(once(label).chain(names)))
+                    .block(block)
+                    .render(attachment_area, buf);
+            }
         }
 
         let [left, right] = Layout::horizontal(
             [Constraint::Length(30), Constraint::Fill(1)]
         ).areas(area);
 
+        let [folder_area, left] = Layout::vertical(
+            [Constraint::Length(self.folders.len() as u16 + 1), Constraint::Fill(1)]
+        ).areas(left);
+        state.areas = Areas::default();
+
+        let rows = self.rows(state);
+        let shown = state.list_state.selected()
+            .and_then(|x| rows.get(x))
+            .filter(|_| state.draft.is_none())
+            .map(|&row| self.shown(row, state))
+            .unwrap_or_default();
+        for email in shown {
+            self.decode(email, state);
+        }
+
+        let folders = self.folders.iter().map(|folder| match self.unread_count(folder, state) {
+            0 => folder_name(folder).to_string(),
+            unread => format!("{} ({})", folder_name(folder), unread),
+        }).collect::<Vec<_>>();
+        let folder_block = Block::new().borders(Borders::TOP | Borders::LEFT);
+        let (folder_block, current_style, selected) = match state.moving {
+            Some(target) => (folder_block.title("Déplacer vers…").yellow(), Style::from((Color::Yellow, Modifier::BOLD)), Some(target)),
+            None => {
+                let focused = state.focus == Focus::Folders;
+                let block = if focused { folder_block.title("Dossiers").green() } else { folder_block.title("Dossiers") };
+                let style = if focused { Style::from((Color::Green, Modifier::BOLD)) } else { Style::from(Modifier::BOLD) };
+                (block, style, self.folders.iter().position(|x| *x == state.folder))
+            }
+        };
+        state.areas.folders = folder_block.inner(folder_area);
+        let folder_list = List::new(folders)
+            .highlight_style(current_style)
+            .highlight_symbol("▸ ")
+            .highlight_spacing(HighlightSpacing::Always)
+            .block(folder_block);
+        StatefulWidget::render(folder_list, folder_area, buf, &mut ListState::default().with_selected(selected));
+
+        let unread = self.unread_count(&state.folder, state);
+        let title = if unread > 0 {
+            format!("{} ({} {})", folder_name(&state.folder), unread, if unread == 1 { "non lu" } else { "non lus" })
+        } else {
+            folder_name(&state.folder).to_string()
+        };
+        let prompt = if state.searching || !state.search.is_empty() {
+            format!("/{}{}", state.search, if state.searching { "_" } else { "" })
+        } else {
+            "</> rechercher".to_string()
+        };
         let left_block = Block::new()
             .borders(Borders::TOP | Borders::LEFT | Borders::BOTTOM)
-            .title("Courriels");
+            .border_set(symbols::border::Set {
+                top_left: symbols::line::NORMAL.vertical_right,
+                ..symbols::border::PLAIN
+            })
+            .title(title)
+            .title_bottom(prompt);
+        state.areas.list = left_block.inner(left);
 
         let right_border_set = symbols::border::Set {
             top_left: symbols::line::NORMAL.horizontal_down,
             bottom_left: symbols::line::NORMAL.horizontal_up,
             ..symbols::border::PLAIN
         };
+        let nothing_shown = |message: &'static str, buf: &mut Buffer| {
+            let block = Block::new()
+                .border_set(right_border_set)
+                .borders(Borders::all())
+                .title("Courriel actuel")
+                .title(Line::from(KEYS).right_aligned());
+            let par = Paragraph::new(message).green().centered().block(block);
+            par.render(right, buf);
+        };
 
 
-        let left_list = email_list(&self.emails, left_block);
-        match state.list_state.selected() {
-            None => {
-                let block = Block::new()
-                    .border_set(right_border_set)
-                    .borders(Borders::all())
-                    .title("Courriel actuel");
-                let par = Paragraph::new("No email selected!").green().centered().block(block);
-                par.render(right, buf);
-            },
-            Some(sel) => render_body(right, buf, &self.emails[sel])
+        let left_list = email_list(self, &rows, state, left_block);
+        let attachments = self.attachments(state);
+        match (&state.draft, state.list_state.selected().and_then(|x| rows.get(x))) {
+            (Some(draft), _) => ComposeForm { draft }.render(right, buf),
+            (None, None) => {
+                let message = if !state.search.trim().is_empty() {
+                    "Aucun courriel ne correspond à la recherche"
+                } else if rows.is_empty() {
+                    "Aucun courriel dans ce dossier"
+                } else {
+                    "No email selected!"
+                };
+                nothing_shown(message, buf)
+            }
+            (None, Some(&Row::Written(n))) => {
+                let email = Email::written(&state.sent[n]);
+                let text = highlight(email.body_text(|_| Legibility::Legible, 0), &state.query());
+                render_body(right, buf, &email, &self.dates, text, "Courriel envoyé".to_string(), &[], state)
+            }
+            (None, Some(&Row::Email(email, _))) => {
+                let text = highlight(self.body_text(email, state), &state.query());
+                render_body(right, buf, &self.emails[email], &self.dates, text, "Courriel actuel".to_string(), &attachments, state)
+            }
+            (None, Some(&Row::Thread(t))) => {
+                let messages = self.messages(t, state);
+                let title = format!("Conversation ({} courriels)", messages.len());
+                let text = highlight(self.thread_text(t, state), &state.query());
+                render_body(right, buf, &self.emails[messages[0].0], &self.dates, text, title, &attachments, state)
+            }
         };
 
+        match attachments.get(state.attachment) {
+            Some(attachment) if state.viewing => {
+                let [inner] = Layout::vertical([Constraint::Fill(1)]).margin(1).areas(right);
+                let max_scroll = attachment.line_count(inner.width).saturating_sub(inner.height as usize);
+                state.viewer_scroll = state.viewer_scroll.min(max_scroll.try_into().unwrap_or(u16::MAX));
+                Viewer { attachment, scroll: state.viewer_scroll }.render(right, buf);
+            }
+            _ => state.viewing = false,
+        }
+
         left_list.render(left, buf, &mut state.list_state);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(source: &str) -> Result<Vec<(String, String, usize)>> {
+        let headers = parse_headers(Path::new("test.email"), &mut source.lines().enumerate())?;
+        Ok(headers.into_iter().map(|x| (x.key, x.value, x.line)).collect())
+    }
+
+    fn header(key: &str, value: &str, line: usize) -> (String, String, usize) {
+        (key.to_string(), value.to_string(), line)
+    }
+
+    /// Reads `source` as the file `test.email`, the error naming it so.
+    fn email(source: &str) -> Result<Email, String> {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("test.email");
+        std::fs::write(&path, source).unwrap();
+        Email::new_from_file(&path, INBOX)
+            .map_err(|e| format!("{:#}", e).replace(&path.display().to_string(), "test.email"))
+    }
+
+    #[test]
+    fn headers_stop_at_the_first_empty_line() {
+        let source = "From: a\nSubject:  b \n\nTo: c";
+        let mut lines = source.lines().enumerate();
+        let found = parse_headers(Path::new("test.email"), &mut lines).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(lines.next(), Some((3, "To: c")));
+        assert_eq!(headers(source).unwrap(), vec![header("From", "a", 1), header("Subject", "b", 2)]);
+    }
+
+    #[test]
+    fn folded_headers() {
+        assert_eq!(headers("To: a,\n  b,\n\tc\nCc:\n d").unwrap(), vec![header("To", "a, b, c", 1), header("Cc", "d", 4)]);
+    }
+
+    #[test]
+    fn header_errors() {
+        assert_eq!(headers(" a\nFrom: b").unwrap_err().to_string(), "test.email:1: the headers start with a continuation line");
+        assert_eq!(headers("From: a\nsans deux-points").unwrap_err().to_string(), "test.email:2: expected a `key: value` header");
+        assert_eq!(headers("Reply To: a").unwrap_err().to_string(), "test.email:1: `Reply To` is not a header name");
+        assert_eq!(headers(": a").unwrap_err().to_string(), "test.email:1: `` is not a header name");
+    }
+
+    #[test]
+    fn headers_in_any_order() {
+        let email = email("subject: Rappel\nDATE: 1985-04-20\nCc: c@isras.ch\nto: b@isras.ch\nFrom: a@isras.ch\n\nBonjour").unwrap();
+        assert_eq!(email.id, "test");
+        assert_eq!((email.from.as_str(), email.to.as_str(), email.cc.as_str()), ("a@isras.ch", "b@isras.ch", "c@isras.ch"));
+        assert_eq!(email.subject, "Rappel");
+        assert_eq!(email.date, Some("1985-04-20".parse().unwrap()));
+        assert_eq!(email.body, vec![vec![Run { text: "Bonjour".to_string(), style: Style::new(), fragment: None }]]);
+    }
+
+    #[test]
+    fn optional_and_unknown_headers() {
+        let email = email("From: a\nX-Mailer: Eudora\n\n\nBonjour").unwrap();
+        assert_eq!((email.to.as_str(), email.cc.as_str(), email.subject.as_str()), ("", "", ""));
+        assert_eq!(email.date, None);
+        assert!(!email.read);
+        assert!(email.attachments.is_empty());
+        assert_eq!(email.delivery, Delivery::Immediate);
+        // The empty lines between the headers and the body are left out
+        assert_eq!(email.body.len(), 1);
+    }
+
+    #[test]
+    fn email_errors() {
+        assert_eq!(email("From: a\nTo: b\nfrom: c\n\n").unwrap_err(), "test.email:3: `from` is given twice");
+        assert_eq!(email("Date: 1985-04-20\nDate: 1985-04-21\n\n").unwrap_err(), "test.email:2: `Date` is given twice");
+        assert_eq!(email("Read: peut-être\n\n").unwrap_err(), "test.email:1: `Read` should be `yes` or `no`");
+        assert_eq!(email("From: a\n\n{red: b").unwrap_err(), "test.email:3: this `{` is never closed with `}`");
+        assert!(email("From: a\nDate: 20 avril\n\n").unwrap_err().starts_with("test.email:2: "));
+    }
+
+    fn message(id: &str, subject: &str, date: &str) -> Email {
+        Email {
+            id: id.to_string(),
+            in_reply_to: None,
+            thread: None,
+            read: false,
+            folder: INBOX.to_string(),
+            attachments: vec![],
+            delivery: Delivery::Immediate,
+            body: vec![],
+            from: String::new(),
+            to: String::new(),
+            cc: String::new(),
+            subject: subject.to_string(),
+            date: Some(date.parse().unwrap()),
+        }
+    }
+
+    fn reply(id: &str, subject: &str, date: &str, to: &str) -> Email {
+        Email { in_reply_to: Some(to.to_string()), ..message(id, subject, date) }
+    }
+
+    /// The ids of each conversation, with their depths.
+    fn threads(emails: &[Email]) -> Vec<Vec<(&str, usize)>> {
+        build_threads(emails).iter()
+            .map(|thread| thread.messages.iter().map(|&(i, depth)| (emails[i].id.as_str(), depth)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn base_subjects() {
+        assert_eq!(base_subject("RE: Proposition"), "Proposition");
+        assert_eq!(base_subject(" re:RE :  Proposition "), "Proposition");
+        assert_eq!(base_subject("TR: RE: Proposition"), "Proposition");
+        assert_eq!(base_subject("Re"), "Re");
+        assert_eq!(base_subject("Rendez-vous"), "Rendez-vous");
+        assert_eq!(base_subject("Trésor"), "Trésor");
+        assert_eq!(base_subject("Réunion"), "Réunion");
+    }
+
+    #[test]
+    fn replies_nest_under_what_they_answer() {
+        let emails = [
+            reply("c", "Autre chose", "1985-04-12", "b"),
+            message("a", "Proposition", "1985-04-10"),
+            reply("b", "RE: Proposition", "1985-04-11", "a"),
+            reply("d", "RE: Proposition", "1985-04-13", "a"),
+        ];
+        assert_eq!(threads(&emails), vec![vec![("a", 0), ("b", 1), ("c", 2), ("d", 1)]]);
+    }
+
+    #[test]
+    fn thread_header_groups_emails() {
+        let emails = [
+            Email { thread: Some("alma".to_string()), ..message("a", "Rapport", "1985-04-10") },
+            Email { thread: Some("alma".to_string()), ..message("b", "Résultats", "1985-04-11") },
+            // In the conversation of the email it answers
+            reply("c", "Merci", "1985-04-12", "b"),
+            message("d", "Rapport", "1985-04-09"),
+        ];
+        assert_eq!(threads(&emails), vec![vec![("a", 0), ("b", 1), ("c", 2)], vec![("d", 0)]]);
+    }
+
+    #[test]
+    fn subjects_group_emails_without_headers() {
+        let emails = [
+            message("b", "RE: Budget", "1985-04-10"),
+            message("a", "budget", "1985-04-11"),
+            message("c", "TR: Budget", "1985-04-12"),
+            message("d", "Autre", "1985-04-13"),
+        ];
+        // The email that isn't an answer starts it, even when written after
+        assert_eq!(threads(&emails), vec![vec![("d", 0)], vec![("a", 0), ("b", 1), ("c", 1)]]);
+    }
+
+    #[test]
+    fn reply_to_a_missing_email() {
+        let emails = [
+            message("a", "Proposition", "1985-04-10"),
+            reply("b", "RE: Proposition", "1985-04-11", "absent"),
+            reply("c", "Question", "1985-04-12", "absent"),
+            reply("d", "Boucle", "1985-04-13", "d"),
+        ];
+        assert_eq!(threads(&emails), vec![vec![("d", 0)], vec![("c", 0)], vec![("a", 0), ("b", 1)]]);
+    }
+
+    #[test]
+    fn folders() {
+        let (emails, folders) = load_mailbox(Path::new("tests/fixtures/emails")).unwrap();
+        assert_eq!(folders, STANDARD_FOLDERS);
+        let found = emails.iter().map(|x| (x.id.as_str(), x.folder.as_str(), x.read)).collect::<Vec<_>>();
+        // Those the characters sent are read already
+        assert_eq!(found, vec![("corrupted", INBOX, false), ("envoye", SENT, true)]);
+    }
+
+    fn text(line: &Line) -> String {
+        line.spans.iter().map(|x| x.content.as_ref()).collect()
+    }
+
+    #[test]
+    fn corrupted_portions() {
+        let email = Email::new_from_file(Path::new("tests/fixtures/emails/corrupted.email"), INBOX).unwrap();
+        assert_eq!(email.portions(), HashSet::from([0, 1]));
+
+        let first = |fragment| if fragment == 0 { Legibility::Legible } else { Legibility::Corrupted };
+        let body = email.body_text(first, 0);
+        assert_eq!(text(&body.lines[0]), "Ce paragraphe est toujours lisible.");
+        assert_eq!(text(&body.lines[2]), "Celui-ci ne l'est qu'une fois le premier fragment installé.");
+        for (line, legible) in [(&body.lines[4], "Celui-là attend le deuxième,"), (&body.lines[5], "sur deux lignes.")] {
+            let glitched = text(line);
+            assert_eq!(glitched.chars().count(), legible.chars().count());
+            // The spaces stay, so that the text wraps the same once decoded
+            for (c, original) in glitched.chars().zip(legible.chars()) {
+                assert!(if original == ' ' { c == ' ' } else { GLITCHES.contains(&c) }, "`{}` in `{}`", c, glitched);
+            }
+            assert!(line.spans.iter().all(|x| x.style.fg == Some(Color::Red)));
+        }
+
+        // Decoding ends legible
+        let decoded = email.body_text(|_| Legibility::Decoding(1.0), 7);
+        assert_eq!(text(&decoded.lines[5]), "sur deux lignes.");
+
+        // Searching only finds what can be read
+        assert!(email.matches(&fold_str("premier"), |x| x == 0));
+        assert!(!email.matches(&fold_str("premier"), |_| false));
+        assert!(email.matches(&fold_str("lisible"), |_| false));
+    }
+
+    #[test]
+    fn folding() {
+        assert_eq!(fold_str("Ève À Ç œ ’"), fold_str("eve a c œ '"));
+        assert_eq!(fold_str("ÉVLWÉÈJDJ").into_iter().collect::<String>(), "evlweejdj");
+        // One character for one, even when the accent is written apart
+        assert_eq!(fold_str("E\u{301}ve").len(), 4);
+    }
+
+    #[test]
+    fn finding() {
+        let text = fold_str("Ève écrit à eve.chavanne@isras.ch");
+        assert_eq!(find_all(&text, &fold_str("eve")), vec![0..3, 12..15]);
+        assert_eq!(find_all(&text, &fold_str("ECRIT")), vec![4..9]);
+        assert_eq!(find_all(&fold_str("aaaa"), &fold_str("aa")), vec![0..2, 1..3, 2..4]);
+        assert_eq!(find_all(&text, &[]), vec![]);
+        assert_eq!(find_all(&fold_str("ab"), &fold_str("abc")), vec![]);
+    }
+
+    #[test]
+    fn highlighting() {
+        let text = Text::from(Line::from(vec![Span::raw("Chère "), Span::styled("Ève", Modifier::BOLD)]));
+        let line = &highlight(text, &fold_str("re e")).lines[0];
+        assert_eq!(line.spans, vec![
+            Span::raw("Chè"),
+            Span::styled("re ", SEARCH_HIGHLIGHT),
+            Span::styled("È", Style::from(Modifier::BOLD).patch(SEARCH_HIGHLIGHT)),
+            Span::styled("ve", Modifier::BOLD),
+        ]);
+        let text = Text::raw("rien");
+        assert_eq!(highlight(text.clone(), &[]), text);
+    }
+
+    #[test]
+    fn conversations_by_date_then_name() {
+        let emails = [
+            Email { date: None, ..message("sans-date", "A", "1985-04-01") },
+            message("b", "B", "1985-04-20"),
+            message("a", "C", "1985-04-20"),
+            message("plus-tard", "D", "1985-04-20 14:32"),
+            message("avant", "E", "1985-04-15 23:59"),
+        ];
+        assert_eq!(threads(&emails), vec![
+            vec![("plus-tard", 0)], vec![("a", 0)], vec![("b", 0)], vec![("avant", 0)], vec![("sans-date", 0)],
+        ]);
+    }
+}
@@ -0,0 +1,460 @@
+//! Prints the emails as handouts for the room, so that the props say what the screen says:
+//!
+//! ```text
+//! escape-ui export [--pdf] [--corrupted] [emails folder] [output folder]
+//! ```
+//!
+//! Each email becomes an HTML page, or an A4 PDF with `--pdf`, named after it in the output folder
+//! (`handouts` by default). Corrupted portions are printed legible, unless `--corrupted`. Dates
+//! are written like the email tab of the scenario picked by `mode.txt` shows them.
+//!
+//! Being run from a shell rather than in the room, it tells on the standard output how many
+//! emails it exported.
+
+use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use crate::email::{printed, Printed};
+use crate::scenario;
+
+pub fn run(args: &[String]) -> Result<()> {
+    let mut pdf = false;
+    let mut corrupted = false;
+    let mut folders = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--pdf" => pdf = true,
+            "--corrupted" => corrupted = true,
+            flag if flag.starts_with("--") => bail!("unknown option `{}`", flag),
+            folder => folders.push(PathBuf::from(folder)),
+        }
+    }
+    let (emails, output) = match &folders[..] {
+        [] => (PathBuf::from("emails"), PathBuf::from("handouts")),
+        [emails] => (emails.clone(), PathBuf::from("handouts")),
+        [emails, output] => (emails.clone(), output.clone()),
+        _ => bail!("usage: escape-ui export [--pdf] [--corrupted] [emails folder] [output folder]"),
+    };
+
+    let mode = String::from_utf8(std::fs::read("./mode.txt").context("mode.txt not found")?)?;
+    let dates = scenario::date_format(&crate::scenario_path(&mode), &emails)?;
+    let emails = printed(&emails, corrupted, &dates).with_context(|| format!("{} folder could not be loaded", emails.display()))?;
+    std::fs::create_dir_all(&output).with_context(|| format!("could not create {}", output.display()))?;
+    for email in &emails {
+        let (path, data) = if pdf {
+            (output.join(format!("{}.pdf", email.id)), to_pdf(email))
+        } else {
+            (output.join(format!("{}.html", email.id)), to_html(email).into_bytes())
+        };
+        std::fs::write(&path, data).with_context(|| format!("could not write {}", path.display()))?;
+    }
+    println!("{} emails exported to {}", emails.len(), output.display());
+    Ok(())
+}
+
+type Rgb = (u8, u8, u8);
+
+/// The ink for a terminal color. White and gray, made to be read on black, are left to the
+/// default ink, and yellow is darkened to be read on paper.
+fn ink(color: Color) -> Option<Rgb> {
+    match color {
+        Color::Black => Some((0, 0, 0)),
+        Color::Red => Some((192, 0, 0)),
+        Color::Green => Some((0, 128, 0)),
+        Color::Yellow => Some((176, 128, 0)),
+        Color::Blue => Some((0, 0, 192)),
+        Color::Magenta => Some((160, 0, 160)),
+        Color::Cyan => Some((0, 128, 128)),
+        Color::DarkGray => Some((128, 128, 128)),
+        Color::LightRed => Some((224, 64, 64)),
+        Color::LightGreen => Some((48, 160, 48)),
+        Color::LightYellow => Some((192, 160, 0)),
+        Color::LightBlue => Some((64, 96, 224)),
+        Color::LightMagenta => Some((192, 64, 192)),
+        Color::LightCyan => Some((32, 160, 160)),
+        Color::Rgb(r, g, b) => Some((r, g, b)),
+        Color::Gray | Color::White | Color::Reset | Color::Indexed(_) => None,
+    }
+}
+
+/// The text and background colors of a style, on paper.
+fn colors(style: Style) -> (Option<Rgb>, Option<Rgb>) {
+    let text = style.fg.and_then(ink);
+    let background = style.bg.and_then(ink);
+    let text = if style.add_modifier.contains(Modifier::DIM) { text.or(Some((128, 128, 128))) } else { text };
+    if style.add_modifier.contains(Modifier::REVERSED) {
+        (Some(background.unwrap_or((255, 255, 255))), Some(text.unwrap_or((0, 0, 0))))
+    } else {
+        (text, background)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn css(style: Style) -> String {
+    let mut css = String::new();
+    let (text, background) = colors(style);
+    if let Some((r, g, b)) = text {
+        css += &format!("color: #{:02x}{:02x}{:02x}; ", r, g, b);
+    }
+    if let Some((r, g, b)) = background {
+        css += &format!("background: #{:02x}{:02x}{:02x}; ", r, g, b);
+    }
+    let modifiers = style.add_modifier;
+    if modifiers.contains(Modifier::BOLD) {
+        css += "font-weight: bold; ";
+    }
+    if modifiers.contains(Modifier::ITALIC) {
+        css += "font-style: italic; ";
+    }
+    match (modifiers.contains(Modifier::UNDERLINED), modifiers.contains(Modifier::CROSSED_OUT)) {
+        (true, true) => css += "text-decoration: underline line-through; ",
+        (true, false) => css += "text-decoration: underline; ",
+        (false, true) => css += "text-decoration: line-through; ",
+        (false, false) => {}
+    }
+    css.trim_end().to_string()
+}
+
+fn to_html(email: &Printed) -> String {
+    let headers: String = email.headers.iter()
+        .map(|(label, value)| format!("<div><b>{}:</b> {}</div>\n", label, escape(value)))
+        .collect();
+    let body = email.body.lines.iter().map(|line| {
+        line.spans.iter().map(|span| {
+            let style = css(line.style.patch(span.style));
+            if style.is_empty() {
+                escape(&span.content)
+            } else {
+                format!("<span style=\"{}\">{}</span>", style, escape(&span.content))
+            }
+        }).collect::<String>()
+    }).collect::<Vec<_>>().join("\n");
+
+    format!(r#"<!DOCTYPE html>
+<html lang="fr">
+<head>
+<meta charset="utf-8">
+<title>{}</title>
+<style>
+@page {{ size: A4; margin: 2cm; }}
+body {{ font-family: "Courier New", Courier, monospace; font-size: 11pt; max-width: 80ch; margin: 2em auto; }}
+.headers {{ border-bottom: 1px solid #888; padding-bottom: 0.5em; margin-bottom: 1em; }}
+.headers b {{ color: #666; }}
+.body {{ white-space: pre-wrap; }}
+</style>
+</head>
+<body>
+<div class="headers">
+{}</div>
+<div class="body">{}</div>
+</body>
+</html>
+"#, escape(&email.subject), headers, body)
+}
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+const FONT_SIZE: f32 = 10.0;
+// Courier is as wide as that for every character, which makes wrapping easy
+const CHAR_WIDTH: f32 = FONT_SIZE * 0.6;
+const LINE_HEIGHT: f32 = FONT_SIZE * 1.25;
+const COLUMNS: usize = ((PAGE_WIDTH - 2.0 * MARGIN) / CHAR_WIDTH) as usize;
+/// The fonts every PDF reader has, so none is embedded.
+const FONTS: [(&[u8], &[u8]); 4] = [
+    (b"F1", b"Courier"),
+    (b"F2", b"Courier-Bold"),
+    (b"F3", b"Courier-Oblique"),
+    (b"F4", b"Courier-BoldOblique"),
+];
+
+/// The character in the encoding of the standard fonts.
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        '\u{a0}'..='\u{ff}' => c as u32 as u8,
+        '€' => 0x80,
+        '…' => 0x85,
+        'Œ' => 0x8c,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        'œ' => 0x9c,
+        '│' => b'|',
+        '░' | '▒' | '▓' | '█' => b'#',
+        _ => b'?',
+    }
+}
+
+/// Splits the line into rows that fit the page, between words when possible.
+fn wrap(line: &Line) -> Vec<Vec<(char, Style)>> {
+    let mut chars: Vec<(char, Style)> = line.spans.iter()
+        .flat_map(|span| span.content.chars().map(move |c| (c, line.style.patch(span.style))))
+        .collect();
+    let mut rows = vec![];
+    while chars.len() > COLUMNS {
+        let end = chars[..=COLUMNS].iter().rposition(|&(c, _)| c == ' ').filter(|&x| x > 0).unwrap_or(COLUMNS);
+        let rest = chars.split_off(end);
+        // The spaces between the rows are left out
+        while chars.last().is_some_and(|&(c, _)| c == ' ') {
+            chars.pop();
+        }
+        rows.push(chars);
+        chars = rest.into_iter().skip_while(|&(c, _)| c == ' ').collect();
+    }
+    rows.push(chars);
+    rows
+}
+
+/// Draws a row of text at the height `y`, in runs of one style.
+fn draw_row(content: &mut Content, row: &[(char, Style)], y: f32) {
+    let mut start = 0;
+    while start < row.len() {
+        let style = row[start].1;
+        let end = row[start..].iter().position(|&(_, x)| x != style).map_or(row.len(), |x| start + x);
+        let x = MARGIN + start as f32 * CHAR_WIDTH;
+        let width = (end - start) as f32 * CHAR_WIDTH;
+        let (text, background) = colors(style);
+        let (r, g, b) = text.unwrap_or((0, 0, 0));
+        let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+
+        if let Some((br, bg, bb)) = background {
+            content.set_fill_rgb(br as f32 / 255.0, bg as f32 / 255.0, bb as f32 / 255.0);
+            content.rect(x, y - FONT_SIZE * 0.25, width, LINE_HEIGHT).fill_nonzero();
+        }
+        let modifiers = style.add_modifier;
+        let font = match (modifiers.contains(Modifier::BOLD), modifiers.contains(Modifier::ITALIC)) {
+            (false, false) => FONTS[0].0,
+            (true, false) => FONTS[1].0,
+            (false, true) => FONTS[2].0,
+            (true, true) => FONTS[3].0,
+        };
+        let text: Vec<u8> = row[start..end].iter().map(|&(c, _)| win_ansi(c)).collect();
+        content.begin_text()
+            .set_font(Name(font), FONT_SIZE)
+            .set_fill_rgb(r, g, b)
+            .next_line(x, y)
+            .show(Str(&text))
+            .end_text();
+
+        for (modifier, height) in [(Modifier::UNDERLINED, -FONT_SIZE * 0.15), (Modifier::CROSSED_OUT, FONT_SIZE * 0.3)] {
+            if modifiers.contains(modifier) {
+                content.set_stroke_rgb(r, g, b)
+                    .set_line_width(0.5)
+                    .move_to(x, y + height)
+                    .line_to(x + width, y + height)
+                    .stroke();
+            }
+        }
+        start = end;
+    }
+}
+
+fn to_pdf(email: &Printed) -> Vec<u8> {
+    let label = Style::new().add_modifier(Modifier::BOLD).fg(Color::DarkGray);
+    let mut rows: Vec<Vec<(char, Style)>> = vec![];
+    for (name, value) in &email.headers {
+        let line = Line::from(vec![ratatui::text::Span::styled(format!("{}: ", name), label), value.as_str().into()]);
+        rows.extend(wrap(&line));
+    }
+    // Stands for the line under the headers
+    let separator = rows.len();
+    rows.push(vec![]);
+    for line in &email.body.lines {
+        rows.extend(wrap(line));
+    }
+
+    let rows_per_page = ((PAGE_HEIGHT - 2.0 * MARGIN) / LINE_HEIGHT) as usize;
+    let pages: Vec<_> = rows.chunks(rows_per_page).collect();
+
+    let catalog = Ref::new(1);
+    let page_tree = Ref::new(2);
+    let font_ids: Vec<_> = (0..FONTS.len() as i32).map(|x| Ref::new(3 + x)).collect();
+    let first_page = 3 + FONTS.len() as i32;
+    // A page, then its content
+    let page_ids: Vec<_> = (0..pages.len() as i32).map(|x| Ref::new(first_page + 2 * x)).collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog).pages(page_tree);
+    pdf.pages(page_tree).kids(page_ids.iter().copied()).count(pages.len() as i32);
+    for (&(_, font), &id) in FONTS.iter().zip(&font_ids) {
+        pdf.type1_font(id).base_font(Name(font)).encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+    for (n, (rows, &page_id)) in pages.iter().zip(&page_ids).enumerate() {
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut page = pdf.page(page_id);
+        page.parent(page_tree)
+            .media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT))
+            .contents(content_id);
+        let mut resources = page.resources();
+        let mut fonts = resources.fonts();
+        for (&(name, _), &id) in FONTS.iter().zip(&font_ids) {
+            fonts.pair(Name(name), id);
+        }
+        fonts.finish();
+        resources.finish();
+        page.finish();
+
+        let mut content = Content::new();
+        for (i, row) in rows.iter().enumerate() {
+            let y = PAGE_HEIGHT - MARGIN - FONT_SIZE - i as f32 * LINE_HEIGHT;
+            if n * rows_per_page + i == separator {
+                let y = y + LINE_HEIGHT / 2.0;
+                content.set_stroke_rgb(0.5, 0.5, 0.5)
+                    .set_line_width(0.5)
+                    .move_to(MARGIN, y)
+                    .line_to(PAGE_WIDTH - MARGIN, y)
+                    .stroke();
+            }
+            draw_row(&mut content, row, y);
+        }
+        pdf.stream(content_id, &content.finish());
+    }
+    pdf.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use ratatui::text::{Span, Text};
+    use crate::date::DateFormat;
+    use super::*;
+
+    fn email(body: Text<'static>) -> Printed {
+        Printed {
+            id: "test".to_string(),
+            subject: "Clés & <codes>".to_string(),
+            headers: vec![("de", "Ève <eve.chavanne@isras.ch>".to_string()), ("sujet", "Clés & <codes>".to_string())],
+            body,
+        }
+    }
+
+    fn text(row: &[(char, Style)]) -> String {
+        row.iter().map(|&(c, _)| c).collect()
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape(r#"<a href="x">Tom & Jerry</a>"#), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&lt;/a&gt;");
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn html() {
+        let body = Text::from(vec![
+            Line::from(vec![
+                Span::raw("Le code : "),
+                Span::styled("ALMA", Modifier::BOLD),
+                Span::raw(", "),
+                Span::styled("vite", Modifier::ITALIC),
+                Span::raw(" "),
+                Span::styled("<CORROMPU>", Color::Red),
+            ]),
+            Line::raw(""),
+            Line::styled("tout en gras", Modifier::BOLD),
+        ]);
+        let html = to_html(&email(body));
+        assert!(html.contains("<title>Clés &amp; &lt;codes&gt;</title>"));
+        assert!(html.contains("<div><b>de:</b> Ève &lt;eve.chavanne@isras.ch&gt;</div>"));
+        assert!(html.contains(concat!(
+            r#"<div class="body">Le code : <span style="font-weight: bold;">ALMA</span>, "#,
+            r#"<span style="font-style: italic;">vite</span> <span style="color: #c00000;">&lt;CORROMPU&gt;</span>"#,
+            "\n\n",
+            r#"<span style="font-weight: bold;">tout en gras</span></div>"#,
+        )), "{}", html);
+    }
+
+    #[test]
+    fn styles() {
+        assert_eq!(css(Style::new()), "");
+        assert_eq!(css(Style::new().fg(Color::White)), "");
+        assert_eq!(css(Style::new().fg(Color::Black).bg(Color::Yellow)), "color: #000000; background: #b08000;");
+        assert_eq!(css(Style::new().add_modifier(Modifier::REVERSED)), "color: #ffffff; background: #000000;");
+        assert_eq!(css(Style::new().add_modifier(Modifier::UNDERLINED | Modifier::CROSSED_OUT)), "text-decoration: underline line-through;");
+    }
+
+    #[test]
+    fn wrapping() {
+        let full = "a".repeat(COLUMNS);
+        assert_eq!(wrap(&Line::raw(full.clone())).iter().map(|x| text(x)).collect::<Vec<_>>(), vec![full.clone()]);
+
+        // Between words
+        let words = format!("{}  mot", "a".repeat(COLUMNS - 1));
+        assert_eq!(wrap(&Line::raw(words)).iter().map(|x| text(x)).collect::<Vec<_>>(), vec!["a".repeat(COLUMNS - 1), "mot".to_string()]);
+
+        // In the middle of words too long for a row
+        let long = format!("début {}", "b".repeat(COLUMNS + 5));
+        assert_eq!(wrap(&Line::raw(long)).iter().map(|x| text(x)).collect::<Vec<_>>(), vec![
+            "début".to_string(), "b".repeat(COLUMNS), "b".repeat(5),
+        ]);
+        let long = "c".repeat(2 * COLUMNS + 1);
+        assert_eq!(wrap(&Line::raw(long)).iter().map(|x| x.len()).collect::<Vec<_>>(), vec![COLUMNS, COLUMNS, 1]);
+
+        assert_eq!(wrap(&Line::raw("")), vec![vec![]]);
+        let styled = wrap(&Line::from(vec![Span::raw("a "), Span::styled("b", Color::Red)]).style(Modifier::BOLD));
+        assert_eq!(styled, vec![vec![
+            ('a', Style::new().add_modifier(Modifier::BOLD)),
+            (' ', Style::new().add_modifier(Modifier::BOLD)),
+            ('b', Style::new().fg(Color::Red).add_modifier(Modifier::BOLD)),
+        ]]);
+    }
+
+    #[test]
+    fn french_in_pdf() {
+        let encode = |text: &str| text.chars().map(win_ansi).collect::<Vec<_>>();
+        assert_eq!(encode("Ève"), [0xc8, b'v', b'e']);
+        assert_eq!(encode("œuvre"), [0x9c, b'u', b'v', b'r', b'e']);
+        assert_eq!(encode("l’été"), [b'l', 0x92, 0xe9, b't', 0xe9]);
+        assert_eq!(encode("« ça »"), [0xab, b' ', 0xe7, b'a', b' ', 0xbb]);
+        assert_eq!(encode("Œ… 5 €"), [0x8c, 0x85, b' ', b'5', b' ', 0x80]);
+        assert_eq!(encode("│ █ → "), [b'|', b' ', b'#', b' ', b'?', b' ']);
+    }
+
+    #[test]
+    fn pdf() {
+        let body = Text::from(vec![Line::raw("Bonjour Ève"); 100]);
+        let pdf = to_pdf(&email(body));
+        assert!(pdf.starts_with(b"%PDF-"));
+        // Two headers, the separator and the body don't fit on one page
+        let pages = pdf.windows(b"/Type /Page\n".len()).filter(|x| *x == b"/Type /Page\n").count();
+        assert_eq!(pages, 2);
+    }
+
+    #[test]
+    fn dates_like_the_scenario() {
+        let folder = tempfile::tempdir().unwrap();
+        std::fs::write(folder.path().join("rappel.email"), "from: a\ndate: 1985-04-20 14:32\n\nBonjour").unwrap();
+        let scenario = folder.path().join("scenario.toml");
+        std::fs::write(&scenario, format!(r#"
+            home = "email"
+
+            [[tab]]
+            kind = "email"
+            name = "email"
+            folder = "{}"
+            date_time_format = "%d/%m/%Y à %Hh%M"
+        "#, folder.path().display())).unwrap();
+
+        let dates = scenario::date_format(&scenario, folder.path()).unwrap();
+        assert_eq!(printed_date(folder.path(), &dates), "20/04/1985 à 14h32");
+
+        // Another folder than the tab's
+        let dates = scenario::date_format(&scenario, Path::new("emails")).unwrap();
+        assert_eq!(printed_date(folder.path(), &dates), "20 avril 1985, 14:32");
+    }
+
+    fn printed_date(folder: &Path, dates: &DateFormat) -> String {
+        let printed = printed(folder, false, dates).unwrap();
+        assert_eq!(printed[0].headers[1].0, "le");
+        printed[0].headers[1].1.clone()
+    }
+}
@@ -7,9 +7,10 @@ use ratatui::symbols;
 use ratatui::symbols::border;
 use ratatui::text::ToLine;
 use ratatui::widgets::{Paragraph, StatefulWidgetRef};
-use crate::model::Tab;
+use crate::model::{Tab, Transition};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FailedInstallState {
 }
 
@@ -63,9 +64,9 @@ impl StatefulWidgetRef for FailedInstall {
 }
 
 impl Tab for FailedInstall {
-    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<usize> {
+    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<Transition> {
         if let Event::Key(KeyEvent { code: KeyCode::Char(' '), kind: KeyEventKind::Press, .. }) = event {
-            return Some(1);
+            return Some(Transition::Back);
         }
 
         None
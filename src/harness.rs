@@ -0,0 +1,422 @@
+//! Plays the room without a terminal, a sound card or USB keys, to check whole walkthroughs.
+
+use std::path::Path;
+use std::time::Duration;
+use std::sync::mpsc;
+use awedio::manager::{Manager, Renderer};
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use ratatui::Terminal;
+use crate::scenario::{Scenario, FRAGMENT_COUNT};
+use crate::control::Command;
+use crate::snapshot::Snapshot;
+use crate::usb::{UsbBackend, UsbEvent};
+use crate::{App, TabState};
+
+/// Hands the app the keys the script plugs in.
+struct FakeUsb {
+    events: mpsc::Receiver<UsbEvent>,
+}
+
+impl UsbBackend for FakeUsb {
+    fn poll(&mut self) -> Vec<UsbEvent> {
+        self.events.try_iter().collect()
+    }
+}
+
+/// Something the players do.
+#[derive(Debug, Clone)]
+pub enum Step {
+    Key(KeyCode),
+    /// Types every character, without pressing enter.
+    Type(&'static str),
+    Plug(&'static str),
+    Unplug(&'static str),
+}
+
+pub struct Harness {
+    pub app: App,
+    pub states: Vec<TabState>,
+    terminal: Terminal<TestBackend>,
+    usb: mpsc::Sender<UsbEvent>,
+    // Nothing is ever rendered, but the sounds need somewhere to go
+    _renderer: Renderer,
+}
+
+impl Harness {
+    pub fn new(scenario_path: &Path) -> Self {
+        let (manager, renderer) = Manager::new();
+        let (usb, events) = mpsc::channel();
+        // No game master, the sender is dropped right away
+        let (_, control) = mpsc::channel();
+
+        let scenario = Scenario::load(scenario_path, &manager).unwrap();
+        let mut app = App::new(scenario_path.to_path_buf(), Box::new(FakeUsb { events }), control, manager);
+        let states = app.start(scenario);
+
+        let mut harness = Self {
+            app,
+            states,
+            terminal: Terminal::new(TestBackend::new(120, 30)).unwrap(),
+            usb,
+            _renderer: renderer,
+        };
+        harness.step(None);
+        harness
+    }
+
+    fn step(&mut self, event: Option<Event>) {
+        assert!(self.app.update(event, &mut self.states), "the app quit");
+        self.app.draw(&mut self.terminal, &mut self.states).unwrap();
+    }
+
+    pub fn key(&mut self, code: KeyCode) {
+        self.step(Some(Event::Key(KeyEvent::new(code, KeyModifiers::NONE))));
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.key(KeyCode::Char(c));
+        }
+    }
+
+    pub fn usb(&mut self, label: &str, plugged: bool) {
+        self.usb.send(UsbEvent { label: label.to_string(), plugged }).unwrap();
+        self.step(None);
+    }
+
+    pub fn run(&mut self, script: &[Step]) {
+        for step in script {
+            match step {
+                Step::Key(code) => self.key(*code),
+                Step::Type(text) => self.type_text(text),
+                Step::Plug(label) => self.usb(label, true),
+                Step::Unplug(label) => self.usb(label, false),
+            }
+        }
+    }
+
+    /// What was drawn last, one line per row.
+    pub fn screen(&self) -> String {
+        let buffer = self.terminal.backend().buffer();
+        buffer.content()
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn assert_shows(&self, text: &str) {
+        let screen = self.screen();
+        assert!(screen.contains(text), "`{}` is not on screen:\n{}", text, screen);
+    }
+
+    pub fn tab_kind(&self) -> &'static str {
+        self.app.tabs[self.app.current_tab].kind()
+    }
+}
+
+/// Arrow keys moving the time trial cursor from `from` to `to`.
+fn arrows(from: (usize, usize), to: (usize, usize)) -> Vec<Step> {
+    let horizontal = if to.0 > from.0 { KeyCode::Right } else { KeyCode::Left };
+    let vertical = if to.1 > from.1 { KeyCode::Down } else { KeyCode::Up };
+    std::iter::repeat_n(Step::Key(horizontal), from.0.abs_diff(to.0))
+        .chain(std::iter::repeat_n(Step::Key(vertical), from.1.abs_diff(to.1)))
+        .collect()
+}
+
+/// Where a wire of the time trial starts, and where it should go.
+type Wire = ((usize, usize), (usize, usize));
+
+/// Drags each wire of the time trial from its start to its end.
+fn connect(mut cursor: (usize, usize), wires: &[Wire]) -> Vec<Step> {
+    let mut script = vec![];
+    for &(start, end) in wires {
+        script.extend(arrows(cursor, start));
+        script.push(Step::Key(KeyCode::Enter));
+        script.extend(arrows(start, end));
+        script.push(Step::Key(KeyCode::Enter));
+        cursor = end;
+    }
+    script
+}
+
+fn scenario(source: &str) -> tempfile::NamedTempFile {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(&mut file, source.as_bytes()).unwrap();
+    file
+}
+
+#[test]
+fn normal_walkthrough() {
+    let mut harness = Harness::new(Path::new("scenarios/normal.toml"));
+    // `password.txt` is empty, so the computer starts unlocked
+    assert_eq!(harness.tab_kind(), "email");
+
+    harness.run(&[Step::Plug("1-ESCAPE")]);
+    assert_eq!(harness.tab_kind(), "decrypt");
+    harness.run(&[Step::Type("ALMA.PAIX"), Step::Key(KeyCode::Enter), Step::Unplug("1-ESCAPE")]);
+    assert_eq!(harness.tab_kind(), "email");
+    assert_eq!(harness.app.usbs_plugged, [true, false, false, false]);
+    harness.assert_shows("1/4");
+
+    harness.run(&[Step::Plug("2-ESCAPE"), Step::Type("2.5"), Step::Key(KeyCode::Enter), Step::Unplug("2-ESCAPE")]);
+    harness.run(&[Step::Plug("3-ESCAPE")]);
+    harness.assert_shows("Installation réussie");
+    harness.run(&[Step::Key(KeyCode::Char(' ')), Step::Unplug("3-ESCAPE")]);
+    assert_eq!(harness.app.usbs_plugged, [true, true, true, false]);
+    harness.assert_shows("3/4");
+
+    harness.run(&[Step::Plug("4-ESCAPE")]);
+    assert_eq!(harness.tab_kind(), "time_trial");
+    for answer in ["2", "41", "16", "22", "4", "40"] {
+        harness.run(&[Step::Type(answer), Step::Key(KeyCode::Enter)]);
+    }
+    harness.run(&connect((10, 10), &[
+        ((11, 2), (76, 15)),
+        ((28, 2), (61, 15)),
+        ((54, 2), (18, 15)),
+        ((74, 2), (40, 15)),
+    ]));
+
+    assert_eq!(harness.app.usbs_plugged, [true; FRAGMENT_COUNT]);
+    assert_eq!(Some(harness.app.current_tab), harness.app.routes.victory);
+}
+
+#[test]
+fn disabled_walkthrough() {
+    let mut harness = Harness::new(Path::new("scenarios/disabled.toml"));
+    assert_eq!(harness.tab_kind(), "email");
+
+    for label in ["1-ESCAPE", "2-ESCAPE", "3-ESCAPE", "4-ESCAPE"] {
+        harness.run(&[Step::Plug(label)]);
+        assert_eq!(harness.tab_kind(), "failed_install");
+        harness.assert_shows("Installation impossible");
+        harness.run(&[Step::Key(KeyCode::Char(' '))]);
+        assert_eq!(harness.tab_kind(), "email");
+        harness.run(&[Step::Unplug(label)]);
+    }
+
+    assert_eq!(harness.app.usbs_plugged, [false; FRAGMENT_COUNT]);
+    assert!(!harness.screen().contains("/4"));
+}
+
+#[test]
+fn wrong_answer_stays_on_tab() {
+    let mut harness = Harness::new(Path::new("scenarios/normal.toml"));
+    harness.run(&[Step::Plug("1-ESCAPE"), Step::Type("ALMA"), Step::Key(KeyCode::Enter)]);
+
+    assert_eq!(harness.tab_kind(), "decrypt");
+    assert_eq!(harness.app.usbs_plugged, [false; FRAGMENT_COUNT]);
+}
+
+#[test]
+fn key_plugged_while_locked_opens_after_unlock() {
+    let file = scenario(r#"
+        home = "email"
+        victory_enabled = false
+
+        [[tab]]
+        kind = "password"
+        password = "ouvre"
+
+        [[tab]]
+        kind = "email"
+        name = "email"
+        folder = "emails"
+
+        [[tab]]
+        kind = "decrypt"
+        fragment = 1
+        cipher = "ÉVLWÉÈJDJ"
+        answer = "ALMA.PAIX"
+    "#);
+    let mut harness = Harness::new(file.path());
+    assert_eq!(harness.tab_kind(), "password");
+
+    harness.run(&[Step::Plug("1-ESCAPE")]);
+    assert_eq!(harness.tab_kind(), "password");
+
+    harness.run(&[Step::Type("ouvre"), Step::Key(KeyCode::Enter)]);
+    assert_eq!(harness.tab_kind(), "decrypt");
+}
+
+#[test]
+fn emails_are_read_once_chosen() {
+    let mut harness = Harness::new(Path::new("scenarios/normal.toml"));
+    // The first conversation is selected, but nobody looked at it yet
+    harness.assert_shows("Réception (8 non lus)");
+
+    harness.run(&[Step::Key(KeyCode::Down)]);
+    harness.assert_shows("Réception (6 non lus)");
+    harness.run(&[Step::Key(KeyCode::Up), Step::Key(KeyCode::Enter)]);
+    harness.assert_shows("Réception (4 non lus)");
+}
+
+#[test]
+fn sending_the_right_email_opens_a_tab() {
+    let file = scenario(r#"
+        home = "email"
+        victory_enabled = false
+
+        [[tab]]
+        kind = "email"
+        name = "email"
+        folder = "emails"
+
+        [[tab.rule]]
+        to = "lucas.morel@isras.ch"
+        body = "ALMA.PAIX"
+        goto = "decrypt"
+
+        [[tab]]
+        kind = "decrypt"
+        name = "decrypt"
+        fragment = 1
+        cipher = "ÉVLWÉÈJDJ"
+        answer = "ALMA.PAIX"
+    "#);
+    let mut harness = Harness::new(file.path());
+    let send = |body: &'static str| vec![
+        Step::Type("n"),
+        Step::Type("lucas.morel@isras.ch"),
+        Step::Key(KeyCode::Tab),
+        Step::Type("Clé"),
+        Step::Key(KeyCode::Tab),
+        Step::Type(body),
+        Step::Key(KeyCode::Tab),
+        Step::Key(KeyCode::Enter),
+    ];
+
+    harness.run(&send("alma"));
+    assert_eq!(harness.tab_kind(), "email");
+    harness.assert_shows("▸ Envoyés");
+
+    harness.run(&send("C'est alma.paix"));
+    assert_eq!(harness.tab_kind(), "decrypt");
+}
+
+#[test]
+fn resumed_session_carries_on() {
+    let file = scenario(r#"
+        home = "email"
+        duration_minutes = 60
+
+        [[tab]]
+        kind = "email"
+        name = "email"
+        folder = "emails"
+
+        [[tab]]
+        kind = "decrypt"
+        name = "decrypt"
+        fragment = 1
+        cipher = "ÉVLWÉÈJDJ"
+        answer = "ALMA.PAIX"
+    "#);
+    let mut harness = Harness::new(file.path());
+    harness.app.timer.as_mut().unwrap().adjust(-10 * 60);
+    harness.run(&[Step::Plug("1-ESCAPE"), Step::Type("ALMA.PAIX"), Step::Key(KeyCode::Enter)]);
+    harness.run(&[Step::Unplug("1-ESCAPE")]);
+    // Back on the solved puzzle, which isn't where a new session starts
+    harness.app.show(1);
+
+    let folder = tempfile::tempdir().unwrap();
+    let path = folder.path().join("snapshot.json");
+    harness.app.snapshot(&harness.states).save(&path).unwrap();
+
+    let mut resumed = Harness::new(file.path());
+    resumed.app.restore(Snapshot::load(&path).unwrap(), &mut resumed.states).unwrap();
+    assert_eq!(resumed.app.usbs_plugged, [true, false, false, false]);
+    assert_eq!(resumed.app.current_tab, 1);
+    assert_eq!(resumed.tab_kind(), "decrypt");
+    let left = resumed.app.timer.as_ref().unwrap().left();
+    assert!(left <= Duration::from_secs(50 * 60) && left > Duration::from_secs(49 * 60), "{:?} left", left);
+
+    // A snapshot taken before the room was timed doesn't stop the clock
+    let mut snapshot = harness.app.snapshot(&harness.states);
+    snapshot.timer = None;
+    let mut resumed = Harness::new(file.path());
+    resumed.app.restore(snapshot, &mut resumed.states).unwrap();
+    assert!(resumed.app.timer.as_ref().unwrap().left() > Duration::from_secs(59 * 60));
+}
+
+#[test]
+fn game_master_hints_cost_no_time() {
+    let file = scenario(r#"
+        home = "email"
+        duration_minutes = 60
+        hint_cooldown_seconds = 120
+        hint_penalty_seconds = 300
+
+        [[tab]]
+        kind = "email"
+        name = "email"
+        folder = "emails"
+        hints = ["Premier indice", "Deuxième indice", "Troisième indice"]
+    "#);
+    let mut harness = Harness::new(file.path());
+    let left = |harness: &Harness| harness.app.timer.as_ref().unwrap().left();
+
+    harness.app.command(Command::NextHint, &mut harness.states).unwrap();
+    harness.app.command(Command::Hint("Regardez sous le clavier".to_string()), &mut harness.states).unwrap();
+    harness.step(None);
+    assert!(left(&harness) > Duration::from_secs(59 * 60));
+    assert_eq!(harness.app.hint_progress.penalty, Duration::ZERO);
+
+    // Nor do they make the players wait for theirs
+    harness.run(&[Step::Key(KeyCode::Enter), Step::Key(KeyCode::F(1))]);
+    harness.assert_shows("Deuxième indice");
+    assert!(left(&harness) <= Duration::from_secs(55 * 60));
+    harness.run(&[Step::Key(KeyCode::Enter), Step::Key(KeyCode::F(1))]);
+    harness.assert_shows("Prochain indice disponible");
+}
+
+#[test]
+fn email_shortcuts_ignore_case() {
+    let mut harness = Harness::new(Path::new("scenarios/normal.toml"));
+    harness.type_text("M");
+    harness.assert_shows("Déplacer vers…");
+    harness.run(&[Step::Key(KeyCode::Esc), Step::Type("N")]);
+    harness.assert_shows("Nouveau courriel");
+}
+
+
+#[test]
+fn corrupted_emails_decode_once_recovered() {
+    let file = scenario(r#"
+        home = "email"
+        victory_enabled = false
+
+        [[tab]]
+        kind = "email"
+        name = "email"
+        folder = "emails"
+
+        [[tab]]
+        kind = "decrypt"
+        name = "decrypt"
+        fragment = 1
+        cipher = "ÉVLWÉÈJDJ"
+        answer = "ALMA.PAIX"
+    "#);
+    let mut harness = Harness::new(file.path());
+    // Rappel, then Clés de chiffrement with its reply
+    for conversation in [vec![], vec![Step::Key(KeyCode::Down)]] {
+        harness.run(&conversation);
+        assert!(!harness.screen().contains("<CORROMPU>"), "legible before recovery:\n{}", harness.screen());
+    }
+
+    harness.run(&[Step::Plug("1-ESCAPE"), Step::Type("ALMA.PAIX"), Step::Key(KeyCode::Enter), Step::Unplug("1-ESCAPE")]);
+    assert_eq!(harness.tab_kind(), "email");
+    harness.run(&[Step::Key(KeyCode::Up)]);
+    std::thread::sleep(crate::email::DECODING_DURATION);
+    harness.step(None);
+    harness.assert_shows("<CORROMPU>");
+
+    // Their fragments are still missing
+    harness.run(&[Step::Key(KeyCode::Down)]);
+    std::thread::sleep(crate::email::DECODING_DURATION);
+    harness.step(None);
+    assert!(!harness.screen().contains("<CORROMPU>"), "legible before recovery:\n{}", harness.screen());
+}
@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// The hints of every tab, and the rules for revealing them.
+#[derive(Debug, Clone, Default)]
+pub struct Hints {
+    tabs: Vec<Vec<String>>,
+    cooldown: Duration,
+    /// Taken off the timer for each hint revealed.
+    pub penalty: Duration,
+}
+
+/// The hints a group has seen so far.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HintProgress {
+    // Number of hints revealed, per tab
+    revealed: Vec<usize>,
+    /// Total time lost to hints.
+    pub penalty: Duration,
+    #[serde(skip)]
+    last: Option<Instant>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reveal {
+    Hint { text: String, number: usize, total: usize },
+    /// The next hint is available after this long.
+    Cooldown(Duration),
+    Exhausted,
+}
+
+impl Hints {
+    pub fn new(tabs: Vec<Vec<String>>, cooldown: Duration, penalty: Duration) -> Self {
+        Self {
+            tabs,
+            cooldown,
+            penalty,
+        }
+    }
+
+    pub fn has_more(&self, tab: usize, progress: &HintProgress) -> bool {
+        progress.revealed(tab) < self.tabs.get(tab).map_or(0, |x| x.len())
+    }
+
+    /// Reveals the next hint of `tab`. The cooldown and the penalty are for the hints players ask
+    /// for, not those the game master gives.
+    pub fn reveal(&self, tab: usize, progress: &mut HintProgress, by_game_master: bool) -> Reveal {
+        let hints = self.tabs.get(tab).map_or(&[][..], |x| x.as_slice());
+        let revealed = progress.revealed(tab);
+        if revealed >= hints.len() {
+            return Reveal::Exhausted;
+        }
+
+        if !by_game_master {
+            if let Some(last) = progress.last {
+                let elapsed = last.elapsed();
+                if elapsed < self.cooldown {
+                    return Reveal::Cooldown(self.cooldown - elapsed);
+                }
+            }
+        }
+
+        if progress.revealed.len() <= tab {
+            progress.revealed.resize(tab + 1, 0);
+        }
+        progress.revealed[tab] += 1;
+        if !by_game_master {
+            progress.penalty += self.penalty;
+            progress.last = Some(Instant::now());
+        }
+
+        Reveal::Hint {
+            text: hints[revealed].clone(),
+            number: revealed + 1,
+            total: hints.len(),
+        }
+    }
+}
+
+impl HintProgress {
+    fn revealed(&self, tab: usize) -> usize {
+        self.revealed.get(tab).copied().unwrap_or(0)
+    }
+}
@@ -8,32 +8,57 @@ mod music;
 mod decrypt;
 mod successful_install;
 mod failed_install;
+mod scenario;
+mod control;
+mod session_log;
+mod snapshot;
+mod timer;
+mod defeat;
+mod hints;
+mod usb;
+mod attachment;
+mod delivery;
+mod compose;
+mod markup;
+mod date;
+mod export;
+#[cfg(test)]
+mod harness;
 
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
-use std::time::Duration;
-use anyhow::Context;
-use notify::event::{ModifyKind, RemoveKind, RenameMode};
-use notify::EventKind;
+use std::time::{Duration, Instant};
+use anyhow::{bail, Context};
 use crate::email::{EmailProgram, EmailProgramState};
+use serde::{Deserialize, Serialize};
+use crate::control::{Command, Request};
+use crate::scenario::{Scenario, FRAGMENT_COUNT};
+use crate::session_log::Entry;
+use crate::snapshot::Snapshot;
+use crate::timer::{Stopwatch, Timer};
+use crate::delivery::Progress;
+use crate::defeat::Defeat;
+use crate::hints::{HintProgress, Hints, Reveal};
+use crate::usb::{UsbBackend, UsbEvent};
 use ratatui::buffer::Buffer;
-use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::layout::{Constraint, Flex, Layout, Rect};
 use ratatui::prelude::*;
 use ratatui::{crossterm, crossterm::event::{self, KeyCode, KeyEventKind}, DefaultTerminal};
-use ratatui::crossterm::event::{Event, KeyModifiers};
-use ratatui::crossterm::style::Stylize;
+use ratatui::crossterm::event::{DisableMouseCapture, EnableMouseCapture, Event, KeyEvent, KeyModifiers};
+use ratatui::crossterm::execute;
 use ratatui::text::ToLine;
-use ratatui::widgets::{Paragraph, StatefulWidgetRef};
+use ratatui::widgets::{Block, Clear, Paragraph, StatefulWidgetRef, Wrap};
 use crate::decrypt::{Decrypt, DecryptState};
 use crate::failed_install::{FailedInstall, FailedInstallState};
-use crate::model::Tab;
+use crate::model::{Routes, Tab, Transition};
 use crate::music::{MusicPlayer, MusicPlayerState};
 use crate::password::{PasswordEntry, PasswordEntryState};
 use crate::successful_install::{SuccessfulInstall, SuccessfulInstallState};
 use crate::time_trial::{TimeTrial, TimeTrialState};
 use crate::victory::{Victory, VictoryState};
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 enum TabUi {
     Password(PasswordEntry),
     Email(EmailProgram),
@@ -45,6 +70,37 @@ enum TabUi {
     Victory(Victory),
 }
 
+impl TabUi {
+    /// The name of this kind of tab in scenario files.
+    fn kind(&self) -> &'static str {
+        match self {
+            TabUi::Password(_) => "password",
+            TabUi::Email(_) => "email",
+            TabUi::Decrypt(_) => "decrypt",
+            TabUi::Music(_) => "music",
+            TabUi::TimeTrial(_) => "time_trial",
+            TabUi::SuccessfulInstall(_) => "successful_install",
+            TabUi::FailedInstall(_) => "failed_install",
+            TabUi::Victory(_) => "victory",
+        }
+    }
+}
+
+impl TabState {
+    fn kind(&self) -> &'static str {
+        match self {
+            TabState::Password(_) => "password",
+            TabState::Email(_) => "email",
+            TabState::Decrypt(_) => "decrypt",
+            TabState::Music(_) => "music",
+            TabState::TimeTrial(_) => "time_trial",
+            TabState::SuccessfulInstall(_) => "successful_install",
+            TabState::FailedInstall(_) => "failed_install",
+            TabState::Victory(_) => "victory",
+        }
+    }
+}
+
 impl StatefulWidgetRef for TabUi {
     type State = TabState;
 
@@ -103,7 +159,7 @@ impl StatefulWidgetRef for TabUi {
 }
 
 impl Tab for TabUi {
-    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<usize> {
+    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<Transition> {
         match self {
             TabUi::Email(email) => {
                 let TabState::Email(state) = state else {
@@ -157,9 +213,10 @@ impl Tab for TabUi {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 enum TabState {
-    Email(EmailProgramState),
+    // Boxed, being much bigger than the others
+    Email(Box<EmailProgramState>),
     Password(PasswordEntryState),
     Music(MusicPlayerState),
     Decrypt(DecryptState),
@@ -170,10 +227,31 @@ enum TabState {
 }
 
 struct App {
+    scenario_path: PathBuf,
+    last_snapshot: Instant,
     tabs: Vec<TabUi>,
+    routes: Routes,
     current_tab: usize,
-    usbs_plugged: [bool; 5],
-    watcher: mpsc::Receiver<notify::Result<notify::Event>>,
+    // Tabs shown before the current one, for `Transition::Back`
+    history: Vec<usize>,
+    usbs_plugged: [bool; FRAGMENT_COUNT],
+    // The keys physically in the computer, which is not the same as installed
+    usbs_present: [bool; FRAGMENT_COUNT],
+    victory_enabled: bool,
+    timer: Option<Timer>,
+    // Time played, for emails delivered after a while
+    stopwatch: Stopwatch,
+    // Tabs whose puzzle is solved, for emails delivered afterwards
+    solved: HashSet<usize>,
+    // A notification, with when it appeared
+    toast: Option<(String, Instant)>,
+    hints: Hints,
+    hint_progress: HintProgress,
+    // Set by the game master
+    paused: bool,
+    hint: Option<String>,
+    usb: Box<dyn UsbBackend>,
+    control: mpsc::Receiver<Request>,
     sound_manager: awedio::manager::Manager,
 }
 
@@ -194,164 +272,531 @@ impl StatefulWidget for &App {
 
         self.tabs[self.current_tab].render_ref(window_control, buf, &mut state[self.current_tab]);
         let left_span = Span::styled("! AVERTISSEMENT: DISQUE PARTIELLEMENT CORROMPU. CERTAINES DONNÉES PEUVENT ÊTRE PERDUES.", Color::Red);
+        let timer_span = match &self.timer {
+            Some(timer) => {
+                let left = timer.left().as_secs();
+                let style = match left {
+                    0..60 => Style::new().red().add_modifier(Modifier::SLOW_BLINK),
+                    60..300 => Style::new().yellow(),
+                    _ => Style::new(),
+                };
+                Span::styled(format!("   {:02}:{:02}", left / 60, left % 60), style)
+            }
+            None => Span::raw(""),
+        };
         let good_usb_count = self.usbs_plugged.iter().filter(|&&x| x).count();
-        let right_span = if good_usb_count >= 2 && self.usbs_plugged[4] {
-            Span::styled(format!("{}/4", good_usb_count-1), Color::Green)
+        let right_span = if good_usb_count >= 1 && self.victory_enabled {
+            Span::styled(format!("{}/{}", good_usb_count, FRAGMENT_COUNT), Color::Green)
         } else {
             Span::styled("", Style::new())
         };
-        Paragraph::new(Line::from(vec![left_span]).left_aligned()).render(warning, buf);
+        Paragraph::new(Line::from(vec![left_span, timer_span]).left_aligned()).render(warning, buf);
         Paragraph::new(Line::from(vec![right_span]).right_aligned()).render(count, buf);
+
+        if self.hints.has_more(self.current_tab, &self.hint_progress) {
+            let help = Rect { y: area.bottom().saturating_sub(1), height: 1, ..count };
+            Paragraph::new(Line::from("<F1> indice").right_aligned()).dark_gray().render(help, buf);
+        }
+
+        if let Some((toast, _)) = &self.toast {
+            let width = (toast.chars().count() as u16 + 4).min(area.width);
+            let toast_area = Rect {
+                x: area.right().saturating_sub(width + 4),
+                y: area.y + 2,
+                width,
+                height: 3.min(area.height),
+            };
+            Clear.render(toast_area, buf);
+            Paragraph::new(toast.as_str())
+                .centered()
+                .block(Block::bordered())
+                .green()
+                .render(toast_area, buf);
+        }
+
+        if let Some(hint) = &self.hint {
+            let [_, hint_area] = Layout::vertical([Constraint::Fill(1), Constraint::Length(5)])
+                .horizontal_margin(10)
+                .vertical_margin(2)
+                .areas(area);
+            Clear.render(hint_area, buf);
+            Paragraph::new(hint.as_str())
+                .wrap(Wrap { trim: true })
+                .block(Block::bordered().title("Indice").title_bottom("<ENTRÉE>").yellow())
+                .render(hint_area, buf);
+        }
+
+        if self.paused {
+            let [pause_area] = Layout::vertical([Constraint::Length(3)]).flex(Flex::Center).areas(area);
+            let [pause_area] = Layout::horizontal([Constraint::Length(30)]).flex(Flex::Center).areas(pause_area);
+            Clear.render(pause_area, buf);
+            Paragraph::new("SESSION EN PAUSE")
+                .centered()
+                .block(Block::bordered())
+                .yellow()
+                .render(pause_area, buf);
+        }
     }
 }
 
 impl App {
-    pub fn new(tabs: Vec<TabUi>, watcher: mpsc::Receiver<notify::Result<notify::Event>>, manager: awedio::manager::Manager) -> Result<Self, ()> {
-        if tabs.len() == 0 {
-            return Err(());
-        }
-
-        Ok(Self {
-            tabs,
+    pub fn new(scenario_path: PathBuf, usb: Box<dyn UsbBackend>, control: mpsc::Receiver<Request>, manager: awedio::manager::Manager) -> Self {
+        Self {
+            scenario_path,
+            last_snapshot: Instant::now(),
+            tabs: vec![],
+            routes: Routes::new(HashMap::new(), vec![], 0, None),
             current_tab: 0,
-            usbs_plugged: [false; 5],
-            watcher,
+            history: vec![],
+            usbs_plugged: [false; FRAGMENT_COUNT],
+            usbs_present: [false; FRAGMENT_COUNT],
+            victory_enabled: false,
+            timer: None,
+            stopwatch: Stopwatch::default(),
+            solved: HashSet::new(),
+            toast: None,
+            hints: Hints::default(),
+            hint_progress: HintProgress::default(),
+            paused: false,
+            hint: None,
+            usb,
+            control,
             sound_manager: manager,
-        })
+        }
     }
 
-    pub fn run(&mut self, mut terminal: DefaultTerminal, mut states: Vec<TabState>) -> anyhow::Result<()> {
-        loop {
-            terminal.draw(|frame| {
-                if self.current_tab == self.tabs.len() - 1 {
-                    if let TabState::Victory(state) = &mut states[self.current_tab] {
-                        Victory::destroy(frame, state);
-                        return;
+    /// Sets up the tabs of `scenario`, with all progress forgotten, and returns their states.
+    fn start(&mut self, scenario: Scenario) -> Vec<TabState> {
+        self.tabs = scenario.tabs;
+        self.routes = scenario.routes;
+        self.current_tab = scenario.start_tab;
+        self.history.clear();
+        self.usbs_plugged = [false; FRAGMENT_COUNT];
+        self.victory_enabled = scenario.victory_enabled;
+        self.timer = scenario.duration.map(Timer::new);
+        self.stopwatch = Stopwatch::default();
+        self.solved.clear();
+        self.toast = None;
+        self.hints = scenario.hints;
+        self.hint_progress = HintProgress::default();
+        self.paused = false;
+        self.hint = None;
+        scenario.states
+    }
+
+    /// Gets the room ready for the next group, reloading the scenario and the emails from disk.
+    /// If they can't be loaded, the current session is left untouched. Only the control desk can
+    /// ask for it, so that players can't wipe their hint penalties or the timer.
+    fn reset(&mut self, states: &mut Vec<TabState>) -> anyhow::Result<()> {
+        let scenario = Scenario::load(&self.scenario_path, &self.sound_manager)?;
+        // Each group gets its own log
+        session_log::start(Path::new(LOG_FOLDER), &self.scenario_path)?;
+        self.sound_manager.clear();
+        *states = self.start(scenario);
+        Ok(())
+    }
+
+    fn snapshot(&self, states: &[TabState]) -> Snapshot {
+        Snapshot {
+            scenario: self.scenario_path.clone(),
+            current_tab: self.current_tab,
+            history: self.history.clone(),
+            usbs_plugged: self.usbs_plugged,
+            timer: self.timer.clone(),
+            stopwatch: self.stopwatch.clone(),
+            solved: self.solved.clone(),
+            hints: self.hint_progress.clone(),
+            states: states.to_vec(),
+        }
+    }
+
+    /// Picks a session back up where the snapshot left it. The scenario must already be started.
+    fn restore(&mut self, snapshot: Snapshot, states: &mut Vec<TabState>) -> anyhow::Result<()> {
+        let matches = snapshot.states.len() == self.tabs.len()
+            && snapshot.states.iter().zip(&self.tabs).all(|(state, tab)| state.kind() == tab.kind());
+        if !matches {
+            bail!("the snapshot doesn't match the tabs of {}", self.scenario_path.display());
+        }
+        if snapshot.history.iter().chain([&snapshot.current_tab]).any(|&tab| tab >= self.tabs.len()) {
+            bail!("the snapshot refers to a tab that doesn't exist");
+        }
+
+        self.current_tab = snapshot.current_tab;
+        self.history = snapshot.history;
+        self.usbs_plugged = snapshot.usbs_plugged;
+        // A room timed since keeps the scenario's timer
+        if let (Some(_), Some(timer)) = (&self.timer, snapshot.timer) {
+            self.timer = Some(timer);
+        }
+        self.stopwatch = snapshot.stopwatch;
+        self.solved = snapshot.solved;
+        self.hint_progress = snapshot.hints;
+        *states = snapshot.states;
+        Ok(())
+    }
+
+    fn show(&mut self, tab: usize) {
+        if tab != self.current_tab {
+            self.history.push(self.current_tab);
+            self.switch_to(tab);
+        }
+    }
+
+    fn switch_to(&mut self, tab: usize) {
+        session_log::record(Entry::Tab { from: self.current_tab, to: tab, kind: self.tabs[tab].kind() });
+        self.current_tab = tab;
+    }
+
+    fn is_locked(&self) -> bool {
+        matches!(self.tabs[self.current_tab], TabUi::Password(_))
+    }
+
+    fn apply(&mut self, transition: Transition) {
+        session_log::record(Entry::Transition { transition: format!("{:?}", transition) });
+        match transition {
+            Transition::Goto(name) => {
+                if let Some(tab) = self.routes.tab(&name) {
+                    self.show(tab);
+                }
+            }
+            Transition::Back => {
+                if let Some(tab) = self.history.pop() {
+                    self.switch_to(tab);
+                }
+            }
+            Transition::Solved => {
+                self.solved.insert(self.current_tab);
+                let was_locked = self.is_locked();
+                if let Some(fragment) = self.routes.fragment_of(self.current_tab) {
+                    // USB tab redirected, so it's approved!
+                    self.usbs_plugged[fragment] = true;
+                    if let Ok(x) = awedio::sounds::open_file(Path::new("confirm.wav")) {
+                        self.sound_manager.play(x);
                     }
                 }
-                frame.render_stateful_widget(&*self, frame.area(), &mut states);
-            })?;
-
-            if event::poll(Duration::from_millis(50))? {
-                let event = event::read()?;
-                if let Event::Key(key) = event {
-                    if key.kind == KeyEventKind::Press && key.modifiers == KeyModifiers::ALT {
-                        if let KeyCode::Char(code) = key.code {
-                            match code {
-                                'q' => {
-                                    return Ok(());
-                                },
-                                '1'..='4' => {
-                                    self.usbs_plugged[((code as u8)-b'1') as usize] = true;
-                                },
-                                _ => {}
-                            }
+
+                if self.victory_enabled && self.usbs_plugged.iter().all(|&x| x) {
+                    self.apply(Transition::Victory);
+                } else {
+                    self.show(self.routes.home);
+                    if was_locked {
+                        // A key plugged in before unlocking is handled now
+                        if let Some(tab) = self.plugged_tab() {
+                            self.show(tab);
                         }
                     }
                 }
-                match self.tabs[self.current_tab].handle_input(event, &mut states[self.current_tab]) {
-                    Some(new_tab) => {
-                        if self.current_tab >= 2 && self.current_tab < 6 {
-                            // USB tab redirected, so it's approved!
-                            self.usbs_plugged[self.current_tab - 2] = true;
-                            if let Ok(x) = awedio::sounds::open_file(Path::new("confirm.wav")) {
-                                self.sound_manager.play(x);
-                            }
-                        }
+            }
+            Transition::Victory => {
+                if let Some(timer) = &mut self.timer {
+                    // They made it in time
+                    timer.set_paused(true);
+                }
+                if let Some(tab) = self.routes.victory {
+                    self.show(tab);
+                }
+            }
+            Transition::Custom(_) => {}
+        }
+    }
 
-                        self.current_tab = new_tab;
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.stopwatch.set_paused(paused);
+        if let Some(timer) = &mut self.timer {
+            timer.set_paused(paused);
+        }
+    }
 
-                        if new_tab == 1 && self.usbs_plugged.iter().all(|&x| x) { // Redirect back to victory
-                            self.current_tab = self.tabs.len()-1;
-                        }
-                    }
-                    None => {}
+    /// Puts the emails that just became due in their inbox, and tells the players. Corrupted emails
+    /// also get their recovered portions back.
+    fn deliver_emails(&mut self, states: &mut [TabState]) {
+        // The game master may have changed them
+        for (tab, state) in self.tabs.iter_mut().zip(states.iter_mut()) {
+            if let (TabUi::Email(program), TabState::Email(state)) = (tab, state) {
+                program.reload_if_changed(state);
+            }
+        }
+
+        let progress = Progress {
+            usbs_plugged: &self.usbs_plugged,
+            routes: &self.routes,
+            solved: &self.solved,
+            elapsed: self.stopwatch.elapsed(),
+        };
+        let mut subjects = vec![];
+        for (tab, state) in self.tabs.iter().zip(states.iter_mut()) {
+            if let (TabUi::Email(program), TabState::Email(state)) = (tab, state) {
+                subjects.extend(program.deliver(state, &progress));
+                program.recover(state, &progress);
+            }
+        }
+        self.notify_emails(subjects);
+    }
+
+    fn notify_emails(&mut self, subjects: Vec<String>) {
+        if subjects.is_empty() {
+            return;
+        }
+        for subject in &subjects {
+            session_log::record(Entry::Delivery { subject: subject.clone() });
+        }
+        let text = match &subjects[..] {
+            [subject] => format!("Nouveau courriel : {}", subject),
+            _ => format!("{} nouveaux courriels", subjects.len()),
+        };
+        self.toast = Some((text, Instant::now()));
+        if let Ok(x) = awedio::sounds::open_file(Path::new(NEW_EMAIL_SOUND)) {
+            self.sound_manager.play(x);
+        }
+    }
+
+    fn plugged_tab(&self) -> Option<usize> {
+        (0..FRAGMENT_COUNT)
+            .filter(|&fragment| self.usbs_present[fragment])
+            .find_map(|fragment| self.routes.fragment_tab(fragment))
+    }
+
+    fn handle_usb(&mut self, event: UsbEvent) {
+        session_log::record(Entry::Usb { label: event.label.clone(), plugged: event.plugged });
+        let Some(fragment) = event.fragment() else {
+            return;
+        };
+        self.usbs_present[fragment] = event.plugged;
+
+        if self.is_locked() {
+            return;
+        }
+        if event.plugged {
+            if let Some(tab) = self.routes.fragment_tab(fragment) {
+                self.show(tab);
+            }
+        } else {
+            self.show(self.routes.home);
+        }
+    }
+
+    /// Shows the next hint of the current tab, or why there is none.
+    fn reveal_hint(&mut self, by_game_master: bool) -> Result<(), String> {
+        match self.hints.reveal(self.current_tab, &mut self.hint_progress, by_game_master) {
+            Reveal::Hint { text, number, total } => {
+                session_log::record(Entry::Hint { tab: self.current_tab, number, by_game_master });
+                if let (Some(timer), false) = (&mut self.timer, by_game_master) {
+                    timer.adjust(-(self.hints.penalty.as_secs() as i64));
                 }
+                self.hint = Some(format!("({}/{}) {}", number, total, text));
+                Ok(())
             }
+            Reveal::Cooldown(left) => {
+                let left = left.as_secs() + 1;
+                self.hint = Some(format!("Prochain indice disponible dans {}:{:02}.", left / 60, left % 60));
+                Err("the hint is cooling down".to_string())
+            }
+            Reveal::Exhausted => {
+                self.hint = Some("Plus aucun indice disponible.".to_string());
+                Err("this tab has no hints left".to_string())
+            }
+        }
+    }
 
-            if let Ok(Ok(event)) = self.watcher.try_recv() {
-                if let EventKind::Modify(ModifyKind::Name(RenameMode::Both)) = event.kind {
-                    if let Some(x) = event.paths[1].to_str() {
-                        if x.ends_with("-ESCAPE") {
-                            let index = x.chars().skip("/dev/disk/by-label/".len()).next().unwrap() as u8 - b'1';
-                            // Password and email are first, then all 4 USBs
-                            if self.current_tab != 0 {
-                                self.current_tab = index as usize + 2;
-                            }
-                        }
+    fn is_over(&self) -> bool {
+        self.timer.as_ref().is_some_and(|timer| timer.is_over())
+    }
+
+    /// Carries out a game master command, except `Quit` which ends the run loop.
+    fn command(&mut self, command: Command, states: &mut Vec<TabState>) -> Result<(), String> {
+        match command {
+            Command::Goto(name) => {
+                if self.routes.tab(&name).is_none() {
+                    return Err(format!("there is no tab named `{}`", name));
+                }
+                self.apply(Transition::Goto(name));
+            }
+            Command::Plug(fragment) => {
+                if fragment == 0 || fragment > FRAGMENT_COUNT {
+                    return Err(format!("there is no fragment {}", fragment));
+                }
+                self.usbs_plugged[fragment - 1] = true;
+            }
+            Command::Hint(text) => {
+                self.hint = Some(text);
+            }
+            Command::NextHint => {
+                self.reveal_hint(true)?;
+            }
+            Command::Time(seconds) => {
+                let Some(timer) = &mut self.timer else {
+                    return Err("the room isn't timed".to_string());
+                };
+                timer.adjust(seconds);
+            }
+            Command::Pause => {
+                self.set_paused(true);
+            }
+            Command::Resume => {
+                self.set_paused(false);
+            }
+            Command::Reset => {
+                self.reset(states).map_err(|e| format!("{:#}", e))?;
+            }
+            Command::Deliver(id) => {
+                let mut found = false;
+                let mut subjects = vec![];
+                for (tab, state) in self.tabs.iter().zip(states.iter_mut()) {
+                    if let (TabUi::Email(program), TabState::Email(state)) = (tab, state) {
+                        found |= program.contains(&id);
+                        subjects.extend(program.deliver_now(state, &id));
                     }
-                } else if let EventKind::Remove(_) = event.kind {
-                    if let Some(x) = event.paths[0].to_str() {
-                        if x.ends_with("-ESCAPE") {
-                            self.current_tab = 1.min(self.current_tab);
-                        }
+                }
+                if !found {
+                    return Err(format!("there is no email `{}`", id));
+                }
+                self.notify_emails(subjects);
+            }
+            Command::Quit => {}
+        }
+        Ok(())
+    }
+
+    pub fn run(&mut self, mut terminal: DefaultTerminal, mut states: Vec<TabState>) -> anyhow::Result<()> {
+        loop {
+            if self.last_snapshot.elapsed() >= SNAPSHOT_INTERVAL {
+                if let Err(e) = self.snapshot(&states).save(Path::new(SNAPSHOT_FILE)) {
+                    session_log::record(Entry::Error { message: format!("{:#}", e) });
+                }
+                self.last_snapshot = Instant::now();
+            }
+
+            self.draw(&mut terminal, &mut states)?;
+
+            let event = if event::poll(Duration::from_millis(50))? {
+                Some(event::read()?)
+            } else {
+                None
+            };
+            if !self.update(event, &mut states) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn draw<B: Backend>(&self, terminal: &mut Terminal<B>, states: &mut Vec<TabState>) -> std::io::Result<()> {
+        terminal.draw(|frame| {
+            if self.is_over() {
+                frame.render_widget(&Defeat, frame.area());
+                return;
+            }
+            if Some(self.current_tab) == self.routes.victory {
+                if let TabState::Victory(state) = &mut states[self.current_tab] {
+                    Victory::destroy(frame, state);
+                    return;
+                }
+            }
+            frame.render_stateful_widget(self, frame.area(), states);
+        })?;
+        Ok(())
+    }
+
+    /// Moves the room forward by one frame: the clock, what the players pressed if anything, the
+    /// game master's commands and the USB keys. Returns `false` once the app should quit.
+    fn update(&mut self, event: Option<Event>, states: &mut Vec<TabState>) -> bool {
+        self.stopwatch.tick();
+        if let Some(timer) = &mut self.timer {
+            let was_over = timer.is_over();
+            timer.tick();
+            if timer.is_over() && !was_over {
+                session_log::record(Entry::TimeUp);
+            }
+        }
+
+        if let Some(event) = event {
+            if !self.handle_event(event, states) {
+                return false;
+            }
+        }
+
+        if let Ok(request) = self.control.try_recv() {
+            session_log::record(Entry::Command { command: format!("{:?}", request.command) });
+            if request.command == Command::Quit {
+                request.reply(Ok(()));
+                return false;
+            }
+            let result = self.command(request.command.clone(), states);
+            request.reply(result);
+        }
+
+        for event in self.usb.poll() {
+            self.handle_usb(event);
+        }
+
+        self.deliver_emails(states);
+        if self.toast.as_ref().is_some_and(|(_, since)| since.elapsed() >= TOAST_DURATION) {
+            self.toast = None;
+        }
+        true
+    }
+
+    fn handle_event(&mut self, event: Event, states: &mut [TabState]) -> bool {
+        if let Event::Key(key) = event {
+            if key.kind == KeyEventKind::Press {
+                session_log::record(Entry::Key { key: key.code.to_string(), modifiers: key.modifiers.to_string() });
+            }
+            if key.kind == KeyEventKind::Press && key.modifiers == KeyModifiers::ALT {
+                if let KeyCode::Char(code) = key.code {
+                    match code {
+                        'q' => {
+                            return false;
+                        },
+                        '1'..='4' => {
+                            self.usbs_plugged[((code as u8)-b'1') as usize] = true;
+                        },
+                        _ => {}
                     }
                 }
             }
         }
+        if self.paused || self.is_over() {
+            // The players wait for the game master
+        } else if self.hint.is_some() {
+            if let Event::Key(KeyEvent { code: KeyCode::Enter | KeyCode::Esc, kind: KeyEventKind::Press, .. }) = event {
+                self.hint = None;
+            }
+        } else if let Event::Key(KeyEvent { code: KeyCode::F(1), kind: KeyEventKind::Press, .. }) = event {
+            // Players see why they got no hint in the overlay
+            let _ = self.reveal_hint(false);
+        } else if let Some(transition) = self.tabs[self.current_tab].handle_input(event, &mut states[self.current_tab]) {
+            self.apply(transition);
+        }
+        true
     }
 }
 
-fn render(terminal: DefaultTerminal) -> anyhow::Result<()> {
-    use notify::{Event, RecursiveMode, Result, Watcher};
-    let (tx, rx) = mpsc::channel::<Result<Event>>();
-    let mut watcher = notify::recommended_watcher(tx)?;
-    watcher.watch(Path::new("/dev/disk/by-label"), RecursiveMode::NonRecursive)?;
-    let email = EmailProgram::new("./emails".into()).context("./emails folder not found")?;
-    let mdp = String::from_utf8(std::fs::read("./password.txt").context("password.txt not found")?)?.trim().to_string();
+fn render(terminal: DefaultTerminal, resume: bool) -> anyhow::Result<()> {
     let mode = String::from_utf8(std::fs::read("./mode.txt").context("mode.txt not found")?)?;
     let (manager, _backend) = awedio::start()?;
 
-    let ui = if mode.starts_with("disabled") {
-        vec![
-            TabUi::Password(PasswordEntry::new(mdp.clone())),
-            TabUi::Email(email),
-            TabUi::FailedInstall(FailedInstall {}),
-            TabUi::FailedInstall(FailedInstall {}),
-            TabUi::FailedInstall(FailedInstall {}),
-            TabUi::FailedInstall(FailedInstall {}),
-            TabUi::Victory(Victory {}),
-        ]
+    let snapshot = if resume {
+        Some(Snapshot::load(Path::new(SNAPSHOT_FILE))?)
     } else {
-        vec![
-            TabUi::Password(PasswordEntry::new(mdp.clone())),
-            TabUi::Email(email),
-            TabUi::Decrypt(Decrypt {}),
-            TabUi::Music(MusicPlayer::new(Path::new("clairdelune.mp3").to_path_buf())),
-            TabUi::SuccessfulInstall(SuccessfulInstall {}),
-            TabUi::TimeTrial(TimeTrial {}),
-            TabUi::Victory(Victory {}),
-        ]
+        None
     };
-    let states = if mode.starts_with("disabled") {
-        vec![
-            TabState::Password(PasswordEntryState::new()),
-            TabState::Email(EmailProgramState::new()),
-            TabState::FailedInstall(FailedInstallState::new()),
-            TabState::FailedInstall(FailedInstallState::new()),
-            TabState::FailedInstall(FailedInstallState::new()),
-            TabState::FailedInstall(FailedInstallState::new()),
-            TabState::Victory(VictoryState::new()),
-        ]
-    } else {
-        vec![
-            TabState::Password(PasswordEntryState::new()),
-            TabState::Email(EmailProgramState::new()),
-            TabState::Decrypt(DecryptState::new()),
-            TabState::Music(MusicPlayerState::new(manager.clone())),
-            TabState::SuccessfulInstall(SuccessfulInstallState::new()),
-            TabState::TimeTrial(TimeTrialState::Calculations(0, "".into(), false)),
-            TabState::Victory(VictoryState::new()),
-        ]
+
+    // A resumed session keeps playing the scenario it was started with
+    let scenario_path = match &snapshot {
+        Some(snapshot) => snapshot.scenario.clone(),
+        None => scenario_path(&mode),
     };
+    // Started first, so that what's wrong with the room's files is logged while loading them
+    session_log::start(Path::new(LOG_FOLDER), &scenario_path)?;
+    let scenario = Scenario::load(&scenario_path, &manager)?;
 
-    let mut app = App::new(ui, rx, manager).expect("Can't fail creating");
-    if mdp.is_empty() {
-        app.current_tab = 1;
-    }
-    if !mode.starts_with("disabled") {
-        // Enable victory screen
-        app.usbs_plugged[4] = true;
+    let control = control::listen(&scenario.control_address)?;
+    let usb = usb::open(&scenario.usb)?;
+
+    let mut app = App::new(scenario_path, usb, control, manager);
+    let mut states = app.start(scenario);
+    if let Some(snapshot) = snapshot {
+        app.restore(snapshot, &mut states)?;
     }
 
     app.run(terminal, states)?;
@@ -359,12 +804,40 @@ fn render(terminal: DefaultTerminal) -> anyhow::Result<()> {
     Ok(())
 }
 
+const LOG_FOLDER: &str = "logs";
+const SNAPSHOT_FILE: &str = "snapshot.json";
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5);
+const NEW_EMAIL_SOUND: &str = "confirm.wav";
+const TOAST_DURATION: Duration = Duration::from_secs(5);
+
+/// `mode.txt` names the scenario to play, from the `scenarios` folder.
+fn scenario_path(mode: &str) -> PathBuf {
+    let mode = mode.trim();
+    let name = if mode.is_empty() { "normal" } else { mode };
+    Path::new("scenarios").join(format!("{name}.toml"))
+}
+
 fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Printing handouts doesn't start the room
+    if args.first().is_some_and(|x| x == "export") {
+        return export::run(&args[1..]);
+    }
     let mut terminal = ratatui::init();
     terminal.clear()?;
+    // Some players would rather click than type
+    execute!(std::io::stdout(), EnableMouseCapture)?;
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = execute!(std::io::stdout(), DisableMouseCapture);
+        hook(info);
+    }));
 
-    let result = render(terminal);
+    // `--resume` picks the last session back up, after a crash or a reboot
+    let resume = args.iter().any(|x| x == "--resume");
+    let result = render(terminal, resume);
 
+    let _ = execute!(std::io::stdout(), DisableMouseCapture);
     ratatui::restore();
     result?;
 
@@ -0,0 +1,482 @@
+//! The markup of email bodies.
+//!
+//! Each line of the body is a line of the email. Within a line:
+//!
+//! - `*bold*`, `_italic_` and `~red~` work like in Markdown: the delimiters must touch the text
+//!   they surround and not be inside a word, so `eve_chavanne@isras.ch` and `2*3` stay as they
+//!   are. Doubled delimiters, `**like this**`, work the same. Unmatched delimiters are kept.
+//! - `{tags: text}` styles the text with space-separated tags, and can span lines and hold other
+//!   markup. A tag is a color (`red`, `light-blue`, `#ff8800`), `on` followed by a background
+//!   color, `bold`, `italic`, `underline`, `blink`, `reverse`, `dim` or `crossed`. A fragment
+//!   number, as in `{2: text}`, corrupts the text until that fragment is installed.
+//!   Braces with tags that mean nothing, like `{rouge: text}`, are kept as they are written.
+//! - `\` keeps the character after it as it is.
+//!
+//! Lines starting with `>` are quoted, lines starting with `- ` or `* ` are bullets, and lines
+//! between two ```` ``` ```` lines are shown as they are written.
+//!
+//! In this body, the second paragraph is glitched until the first fragment is installed, and
+//! the last two lines until the second one is:
+//!
+//! ```text
+//! Ce paragraphe est toujours lisible.
+//!
+//! {1:Celui-ci ne l'est qu'une fois le premier fragment installé.}
+//!
+//! {2:Celui-là attend le deuxième,
+//! sur deux lignes.}
+//! ```
+
+use ratatui::style::{Color, Modifier, Style};
+use crate::scenario::FRAGMENT_COUNT;
+
+/// Text in one style.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    pub text: String,
+    pub style: Style,
+    /// The fragment that makes it legible, when the text is corrupted.
+    pub fragment: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    /// As given with the lines
+    pub line: usize,
+    pub message: String,
+}
+
+const QUOTE_BAR: &str = "│ ";
+const BULLET: &str = "• ";
+const FENCE: &str = "```";
+
+/// An open `{tags: ...}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Group {
+    style: Style,
+    fragment: Option<usize>,
+    line: usize,
+}
+
+/// Parses the lines of a body, each given with its line number for the errors. Tags that mean
+/// nothing leave their text as it is written, with a warning.
+pub fn parse<'a>(lines: impl IntoIterator<Item=(usize, &'a str)>, warnings: &mut Vec<Error>) -> Result<Vec<Vec<Run>>, Error> {
+    let mut body = vec![];
+    let mut groups: Vec<Group> = vec![];
+    // Where the preformatted block being read started
+    let mut preformatted = None;
+
+    for (number, line) in lines {
+        if line.trim_start().starts_with(FENCE) {
+            preformatted = match preformatted {
+                None => Some(number),
+                Some(_) => None,
+            };
+            continue;
+        }
+        if preformatted.is_some() {
+            let (style, fragment) = group_style(&groups);
+            body.push(merge(vec![Run { text: line.to_string(), style, fragment }]));
+            continue;
+        }
+
+        let (mut runs, rest, base) = block_prefix(line);
+        runs.extend(parse_inline(rest, base, number, &mut groups, warnings)?);
+        body.push(merge(runs));
+    }
+
+    if let Some(line) = preformatted {
+        return Err(Error { line, message: format!("this preformatted block is never closed with {}", FENCE) });
+    }
+    if let Some(group) = groups.last() {
+        return Err(Error { line: group.line, message: "this `{` is never closed with `}`".to_string() });
+    }
+    Ok(body)
+}
+
+/// Splits the quote bars or the bullet off the line. Returns them, what's left to parse, and the
+/// style of the text after them.
+fn block_prefix(line: &str) -> (Vec<Run>, &str, Style) {
+    let mut rest = line;
+    let mut depth = 0;
+    while let Some(quoted) = rest.strip_prefix('>') {
+        depth += 1;
+        rest = quoted.strip_prefix(' ').unwrap_or(quoted);
+    }
+    if depth > 0 {
+        let bars = Run { text: QUOTE_BAR.repeat(depth), style: Style::new().fg(Color::DarkGray), fragment: None };
+        return (vec![bars], rest, Style::new().add_modifier(Modifier::ITALIC));
+    }
+
+    let content = line.trim_start();
+    let indent = &line[..line.len() - content.len()];
+    if let Some(item) = content.strip_prefix("- ").or_else(|| content.strip_prefix("* ")) {
+        let bullet = Run { text: format!("{}{}", indent, BULLET), style: Style::new(), fragment: None };
+        return (vec![bullet], item, Style::new());
+    }
+    (vec![], line, Style::new())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Text(String),
+    /// A run of `*`, `_` or `~`, and whether it can open or close a span.
+    Delimiter { kind: char, raw: String, open: bool, close: bool },
+    GroupOpen(Group),
+    GroupClose,
+}
+
+fn tokenize(line: &str, number: usize, depth: usize, warnings: &mut Vec<Error>) -> Result<Vec<Token>, Error> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = vec![];
+    let mut text = String::new();
+    // Closing braces beyond this are plain text
+    let mut depth = depth;
+    // The braces opened with tags that mean nothing, which close as text too
+    let mut unknown = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => {
+                // A backslash at the end of the line is kept
+                text.push(chars.get(i + 1).copied().unwrap_or('\\'));
+                i += 2;
+                continue;
+            }
+            '*' | '_' | '~' => {
+                let end = chars[i..].iter().position(|&x| x != c).map_or(chars.len(), |x| i + x);
+                let before = i.checked_sub(1).map(|x| chars[x]);
+                let after = chars.get(end).copied();
+                // Like in Markdown, the delimiters must hug the text and not be inside a word
+                let open = after.is_some_and(|x| !x.is_whitespace()) && before.is_none_or(|x| !x.is_alphanumeric());
+                let close = before.is_some_and(|x| !x.is_whitespace()) && after.is_none_or(|x| !x.is_alphanumeric());
+                if open || close {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                    tokens.push(Token::Delimiter { kind: c, raw: chars[i..end].iter().collect(), open, close });
+                } else {
+                    text.extend(&chars[i..end]);
+                }
+                i = end;
+                continue;
+            }
+            '{' => {
+                let tags_end = chars[i + 1..].iter().position(|&x| x == ':').map(|x| i + 1 + x);
+                let tags = tags_end.map(|end| chars[i + 1..end].iter().collect::<String>());
+                let is_group = tags.as_deref().is_some_and(|x| {
+                    !x.trim().is_empty() && x.chars().all(|c| c.is_alphanumeric() || " ,-#".contains(c))
+                });
+                match (tags.filter(|_| is_group).map(|x| parse_tags(&x)), tags_end) {
+                    (Some(Ok((style, fragment))), Some(end)) => {
+                        if let Some(fragment) = fragment.filter(|x| !(1..=FRAGMENT_COUNT).contains(x)) {
+                            return Err(Error { line: number, message: format!("there is no fragment {}", fragment) });
+                        }
+                        tokens.push(Token::Text(std::mem::take(&mut text)));
+                        tokens.push(Token::GroupOpen(Group { style, fragment: fragment.map(|x| x - 1), line: number }));
+                        depth += 1;
+                        i = end + 1;
+                        // The space after the colon is for readability
+                        if chars.get(i) == Some(&' ') {
+                            i += 1;
+                        }
+                        continue;
+                    }
+                    // Kept as written, down to the closing brace
+                    (Some(Err(message)), _) => {
+                        warnings.push(Error { line: number, message });
+                        unknown += 1;
+                    }
+                    _ => {}
+                }
+                text.push(c);
+            }
+            '}' if unknown > 0 => {
+                text.push(c);
+                unknown -= 1;
+            }
+            '}' if depth > 0 => {
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+                tokens.push(Token::GroupClose);
+                depth -= 1;
+            }
+            _ => text.push(c),
+        }
+        i += 1;
+    }
+    tokens.push(Token::Text(text));
+    tokens.retain(|x| *x != Token::Text(String::new()));
+    Ok(tokens)
+}
+
+/// The style and fragment number given by the tags, or what's wrong with them.
+fn parse_tags(tags: &str) -> Result<(Style, Option<usize>), String> {
+    let mut style = Style::new();
+    let mut fragment = None;
+    let mut words = tags.split([' ', ',']).filter(|x| !x.is_empty());
+    while let Some(word) = words.next() {
+        let tag = word.to_lowercase();
+        if let Ok(number) = tag.parse::<usize>() {
+            fragment = Some(number);
+            continue;
+        }
+        style = match tag.as_str() {
+            "bold" => style.add_modifier(Modifier::BOLD),
+            "italic" => style.add_modifier(Modifier::ITALIC),
+            "underline" | "underlined" => style.add_modifier(Modifier::UNDERLINED),
+            "blink" => style.add_modifier(Modifier::SLOW_BLINK),
+            "reverse" => style.add_modifier(Modifier::REVERSED),
+            "dim" => style.add_modifier(Modifier::DIM),
+            "crossed" => style.add_modifier(Modifier::CROSSED_OUT),
+            "on" => {
+                let color = words.next().ok_or("`on` needs a background color")?;
+                style.bg(color.parse().map_err(|_| format!("`{}` is not a color", color))?)
+            }
+            _ => style.fg(word.parse().map_err(|_| format!("`{}` is neither a style nor a color", word))?),
+        };
+    }
+    Ok((style, fragment))
+}
+
+/// The style and fragment of text inside these groups.
+fn group_style(groups: &[Group]) -> (Style, Option<usize>) {
+    groups.iter().fold((Style::new(), None), |(style, fragment), group| {
+        (style.patch(group.style), group.fragment.or(fragment))
+    })
+}
+
+fn parse_inline(line: &str, base: Style, number: usize, groups: &mut Vec<Group>, warnings: &mut Vec<Error>) -> Result<Vec<Run>, Error> {
+    let mut tokens = tokenize(line, number, groups.len(), warnings)?;
+
+    // Pairs each closing delimiter with the nearest opening one of the same kind, within the same
+    // group. Those left alone are plain text.
+    let mut matched = vec![false; tokens.len()];
+    let mut openers: Vec<(usize, usize)> = vec![];
+    let mut depth = groups.len();
+    for i in 0..tokens.len() {
+        match &tokens[i] {
+            Token::GroupOpen(_) => depth += 1,
+            Token::GroupClose => {
+                openers.retain(|&(_, x)| x < depth);
+                depth -= 1;
+            }
+            Token::Delimiter { kind, close, open, .. } => {
+                let opener = openers.iter().rposition(|&(x, d)| d == depth && matches!(&tokens[x], Token::Delimiter { kind: k, .. } if k == kind));
+                match opener {
+                    Some(position) if *close => {
+                        let (x, _) = openers[position];
+                        matched[x] = true;
+                        matched[i] = true;
+                        // Delimiters opened in between can't close anymore
+                        openers.truncate(position);
+                    }
+                    _ if *open => openers.push((i, depth)),
+                    _ => {}
+                }
+            }
+            Token::Text(_) => {}
+        }
+    }
+
+    let mut runs = vec![];
+    let mut bold = false;
+    let mut italic = false;
+    let mut red = false;
+    for (i, token) in tokens.drain(..).enumerate() {
+        let text = match token {
+            Token::Text(text) => text,
+            Token::Delimiter { raw, .. } if !matched[i] => raw,
+            Token::Delimiter { kind, .. } => {
+                match kind {
+                    '*' => bold = !bold,
+                    '_' => italic = !italic,
+                    _ => red = !red,
+                }
+                continue;
+            }
+            Token::GroupOpen(group) => {
+                if group.fragment.is_some() && groups.iter().any(|x| x.fragment.is_some()) {
+                    return Err(Error { line: number, message: "a corrupted portion can't be in another one".to_string() });
+                }
+                groups.push(group);
+                continue;
+            }
+            Token::GroupClose => {
+                groups.pop();
+                continue;
+            }
+        };
+        let (group, fragment) = group_style(groups);
+        let mut style = base.patch(group);
+        if bold {
+            style = style.add_modifier(Modifier::BOLD);
+        }
+        if italic {
+            style = style.add_modifier(Modifier::ITALIC);
+        }
+        if red {
+            style = style.fg(Color::Red);
+        }
+        runs.push(Run { text, style, fragment });
+    }
+    Ok(runs)
+}
+
+/// Joins the runs that look the same, and drops the empty ones.
+fn merge(runs: Vec<Run>) -> Vec<Run> {
+    let mut merged: Vec<Run> = vec![];
+    for run in runs.into_iter().filter(|x| !x.text.is_empty()) {
+        match merged.last_mut() {
+            Some(last) if last.style == run.style && last.fragment == run.fragment => last.text.push_str(&run.text),
+            _ => merged.push(run),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(source: &str) -> Result<Vec<Vec<Run>>, Error> {
+        parse(source.lines().enumerate().map(|(i, x)| (i + 1, x)), &mut vec![])
+    }
+
+    /// The runs of a one-line body.
+    fn runs(source: &str) -> Vec<Run> {
+        let mut body = parse_str(source).unwrap();
+        assert_eq!(body.len(), 1);
+        body.remove(0)
+    }
+
+    fn run(text: &str, style: Style) -> Run {
+        Run { text: text.to_string(), style, fragment: None }
+    }
+
+    fn plain(text: &str) -> Run {
+        run(text, Style::new())
+    }
+
+    fn bold() -> Style {
+        Style::new().add_modifier(Modifier::BOLD)
+    }
+
+    fn italic() -> Style {
+        Style::new().add_modifier(Modifier::ITALIC)
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(runs("a *b* _c_ ~d~"), vec![
+            plain("a "), run("b", bold()), plain(" "), run("c", italic()), plain(" "), run("d", Style::new().fg(Color::Red)),
+        ]);
+        assert_eq!(runs("**b**"), vec![run("b", bold())]);
+    }
+
+    #[test]
+    fn delimiters_inside_words_are_text() {
+        assert_eq!(runs("écrire à eve_chavanne@isras.ch"), vec![plain("écrire à eve_chavanne@isras.ch")]);
+        assert_eq!(runs("2*3*4"), vec![plain("2*3*4")]);
+        assert_eq!(runs("_a_b_"), vec![run("a_b", italic())]);
+    }
+
+    #[test]
+    fn unmatched_delimiters_are_text() {
+        assert_eq!(runs("*a"), vec![plain("*a")]);
+        assert_eq!(runs("a * b"), vec![plain("a * b")]);
+        assert_eq!(runs("*a _b* c_"), vec![run("a _b", bold()), plain(" c_")]);
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(runs(r"\*a\*"), vec![plain("*a*")]);
+        assert_eq!(runs(r"*a \* b*"), vec![run("a * b", bold())]);
+        assert_eq!(runs(r"\\"), vec![plain(r"\")]);
+        assert_eq!(runs(r"\{red: a}"), vec![plain("{red: a}")]);
+        assert_eq!(runs(r"fin\"), vec![plain(r"fin\")]);
+        assert_eq!(runs(r"{red: a\}b}"), vec![run("a}b", Style::new().fg(Color::Red))]);
+    }
+
+    #[test]
+    fn nesting() {
+        assert_eq!(runs("*a _b_ c*"), vec![
+            run("a ", bold()), run("b", bold().add_modifier(Modifier::ITALIC)), run(" c", bold()),
+        ]);
+        assert_eq!(runs("{red: a *b*}"), vec![
+            run("a ", Style::new().fg(Color::Red)), run("b", Style::new().fg(Color::Red).add_modifier(Modifier::BOLD)),
+        ]);
+        assert_eq!(runs("{red: a {blue: b} c}"), vec![
+            run("a ", Style::new().fg(Color::Red)), run("b", Style::new().fg(Color::Blue)), run(" c", Style::new().fg(Color::Red)),
+        ]);
+        // A delimiter can't close across a group
+        assert_eq!(runs("*a {red: b* c}"), vec![plain("*a "), run("b* c", Style::new().fg(Color::Red))]);
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(runs("{white on red: ALERTE}"), vec![run("ALERTE", Style::new().fg(Color::White).bg(Color::Red))]);
+        assert_eq!(runs("{blink reverse: x}"), vec![
+            run("x", Style::new().add_modifier(Modifier::SLOW_BLINK | Modifier::REVERSED)),
+        ]);
+        assert_eq!(runs("{underline, light-green: x}"), vec![
+            run("x", Style::new().fg(Color::LightGreen).add_modifier(Modifier::UNDERLINED)),
+        ]);
+        assert_eq!(runs("{#ff8800: x}"), vec![run("x", Style::new().fg(Color::Rgb(0xff, 0x88, 0)))]);
+    }
+
+    #[test]
+    fn unknown_tags_are_text() {
+        let mut warnings = vec![];
+        let body = parse("a\n{rouge: b} {on: c}\n{red: d {gras: e} f}".lines().enumerate().map(|(i, x)| (i + 1, x)), &mut warnings).unwrap();
+        assert_eq!(body[1], vec![plain("{rouge: b} {on: c}")]);
+        // Their closing brace doesn't close the group around them
+        assert_eq!(body[2], vec![run("d {gras: e} f", Style::new().fg(Color::Red))]);
+        assert_eq!(warnings, vec![
+            Error { line: 2, message: "`rouge` is neither a style nor a color".to_string() },
+            Error { line: 2, message: "`on` needs a background color".to_string() },
+            Error { line: 3, message: "`gras` is neither a style nor a color".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn braces_without_tags_are_text() {
+        assert_eq!(runs("{ a }"), vec![plain("{ a }")]);
+        assert_eq!(runs("a } b"), vec![plain("a } b")]);
+        assert_eq!(runs("{ \"a\": 1 }"), vec![plain("{ \"a\": 1 }")]);
+    }
+
+    #[test]
+    fn corrupted_portions() {
+        let body = parse_str("{2: a\n\nb} c").unwrap();
+        assert_eq!(body, vec![
+            vec![Run { text: "a".to_string(), style: Style::new(), fragment: Some(1) }],
+            vec![],
+            vec![Run { text: "b".to_string(), style: Style::new(), fragment: Some(1) }, plain(" c")],
+        ]);
+        assert_eq!(parse_str("{5: a}").unwrap_err().message, "there is no fragment 5");
+        assert_eq!(parse_str("{1: a {2: b}}").unwrap_err().message, "a corrupted portion can't be in another one");
+        assert_eq!(parse_str("a\n{1: b\nc").unwrap_err(), Error { line: 2, message: "this `{` is never closed with `}`".to_string() });
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(runs("> > a *b*"), vec![
+            run("│ │ ", Style::new().fg(Color::DarkGray)),
+            run("a ", italic()),
+            run("b", italic().add_modifier(Modifier::BOLD)),
+        ]);
+    }
+
+    #[test]
+    fn bullets() {
+        assert_eq!(runs("- a"), vec![plain("• a")]);
+        assert_eq!(runs("  * *b*"), vec![plain("  • "), run("b", bold())]);
+        assert_eq!(runs("-a"), vec![plain("-a")]);
+    }
+
+    #[test]
+    fn preformatted() {
+        let body = parse_str("```\n  *a* {red: b}\n```\n*c*").unwrap();
+        assert_eq!(body, vec![vec![plain("  *a* {red: b}")], vec![run("c", bold())]]);
+        assert_eq!(parse_str("a\n```\nb").unwrap_err(), Error { line: 2, message: "this preformatted block is never closed with ```".to_string() });
+    }
+}
@@ -1,6 +1,56 @@
+use std::collections::HashMap;
 use ratatui::crossterm::event::Event;
 use ratatui::widgets::StatefulWidgetRef;
 
 pub trait Tab: StatefulWidgetRef {
-    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<usize>;
-}
\ No newline at end of file
+    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<Transition>;
+}
+
+/// What a tab wants to happen next. The app decides which tab that actually means.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transition {
+    /// Show the tab with this name.
+    Goto(String),
+    /// Show the tab that was shown before this one.
+    Back,
+    /// The puzzle of this tab is solved.
+    Solved,
+    /// Show the victory screen.
+    Victory,
+    /// Something noteworthy happened, which doesn't change the tab.
+    Custom(String),
+}
+
+/// Where the named tabs and the USB fragments of a scenario are.
+#[derive(Debug, Clone)]
+pub struct Routes {
+    names: HashMap<String, usize>,
+    // The fragment unlocked by each tab
+    fragments: Vec<Option<usize>>,
+    /// The tab players return to after solving a puzzle.
+    pub home: usize,
+    pub victory: Option<usize>,
+}
+
+impl Routes {
+    pub fn new(names: HashMap<String, usize>, fragments: Vec<Option<usize>>, home: usize, victory: Option<usize>) -> Self {
+        Self {
+            names,
+            fragments,
+            home,
+            victory,
+        }
+    }
+
+    pub fn tab(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn fragment_of(&self, tab: usize) -> Option<usize> {
+        self.fragments[tab]
+    }
+
+    pub fn fragment_tab(&self, fragment: usize) -> Option<usize> {
+        self.fragments.iter().position(|&x| x == Some(fragment))
+    }
+}
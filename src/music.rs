@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 use awedio::manager::Manager;
+use serde::{Deserialize, Serialize};
 use ratatui::buffer::Buffer;
 use ratatui::crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
 use ratatui::layout::Rect;
@@ -7,21 +8,20 @@ use ratatui::prelude::*;
 use ratatui::symbols::border;
 use ratatui::text::ToLine;
 use ratatui::widgets::{Block, Paragraph, StatefulWidgetRef};
-use crate::model::Tab;
+use crate::model::{Tab, Transition};
+use crate::session_log;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MusicPlayerState {
     playing: bool,
-    manager: Manager,
     entry: String,
     error: bool,
 }
 
 impl MusicPlayerState {
-    pub fn new(manager: Manager) -> Self {
+    pub fn new() -> Self {
         Self {
             playing: false,
-            manager,
             entry: "".to_string(),
             error: false,
         }
@@ -30,13 +30,17 @@ impl MusicPlayerState {
 
 #[derive(Debug, Clone)]
 pub struct MusicPlayer {
-    file: PathBuf
+    file: PathBuf,
+    answers: Vec<String>,
+    manager: Manager,
 }
 
 impl MusicPlayer {
-    pub fn new(file: PathBuf) -> MusicPlayer {
+    pub fn new(file: PathBuf, answers: Vec<String>, manager: Manager) -> MusicPlayer {
         MusicPlayer {
-            file
+            file,
+            answers,
+            manager,
         }
     }
 }
@@ -81,9 +85,9 @@ impl StatefulWidgetRef for MusicPlayer {
 }
 
 impl Tab for MusicPlayer {
-    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<usize> {
+    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<Transition> {
         if let Event::Key(KeyEvent { code: KeyCode::Char(' '), kind: KeyEventKind::Press, .. }) = event {
-            state.manager.play(awedio::sounds::open_file(&self.file).unwrap());
+            self.manager.clone().play(awedio::sounds::open_file(&self.file).unwrap());
         } else if let Event::Key(KeyEvent { code: KeyCode::Char(c), kind: KeyEventKind::Press, .. }) = event {
             if state.entry.len() < 20 {
                 state.entry.push(c);
@@ -93,8 +97,10 @@ impl Tab for MusicPlayer {
             state.entry.pop();
             state.error = false;
         } else if let Event::Key(KeyEvent { code: KeyCode::Enter, kind: KeyEventKind::Press, .. }) = event {
-            if state.entry == "2.5.".to_string() || state.entry == "2.5".to_string() {
-                return Some(1); // Email program
+            let correct = self.answers.contains(&state.entry);
+            session_log::attempt("music", &state.entry, correct);
+            if correct {
+                return Some(Transition::Solved);
             } else {
                 state.error = true;
             }
@@ -6,9 +6,11 @@ use ratatui::style::Style;
 use ratatui::symbols::border;
 use ratatui::text::ToLine;
 use ratatui::widgets::{Block, Paragraph, StatefulWidgetRef};
-use crate::model::Tab;
+use crate::model::{Tab, Transition};
+use crate::session_log;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PasswordEntryState {
     entry: String,
     error: bool,
@@ -32,6 +34,10 @@ impl PasswordEntry {
     pub fn new(password: String) -> PasswordEntry {
         PasswordEntry { password }
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.password.is_empty()
+    }
 }
 
 impl StatefulWidgetRef for PasswordEntry {
@@ -75,7 +81,7 @@ impl StatefulWidgetRef for PasswordEntry {
 }
 
 impl Tab for PasswordEntry {
-    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<usize> {
+    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<Transition> {
         if let Event::Key(KeyEvent { code: KeyCode::Char(c), kind: KeyEventKind::Press, .. }) = event {
             if state.entry.len() < 20 {
                 state.entry.push(c);
@@ -87,8 +93,10 @@ impl Tab for PasswordEntry {
             state.error = false;
             None
         } else if let Event::Key(KeyEvent { code: KeyCode::Enter, kind: KeyEventKind::Press, .. }) = event {
-            if state.entry == self.password {
-                Some(1) // Email program
+            let correct = state.entry == self.password;
+            session_log::attempt("password", &state.entry, correct);
+            if correct {
+                Some(Transition::Solved)
             } else {
                 state.error = true;
                 None
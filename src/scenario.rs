@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{bail, Context, Result};
+use awedio::manager::Manager;
+use serde::Deserialize;
+use toml::Spanned;
+use crate::decrypt::{Decrypt, DecryptState};
+use crate::compose::Rule;
+use crate::date::DateFormat;
+use crate::email::{EmailProgram, EmailProgramState};
+use crate::failed_install::{FailedInstall, FailedInstallState};
+use crate::hints::Hints;
+use crate::model::Routes;
+use crate::music::{MusicPlayer, MusicPlayerState};
+use crate::password::{PasswordEntry, PasswordEntryState};
+use crate::successful_install::{SuccessfulInstall, SuccessfulInstallState};
+use crate::time_trial::{TimeTrial, TimeTrialState};
+use crate::usb::UsbConfig;
+use crate::victory::{Victory, VictoryState};
+use crate::{TabState, TabUi};
+
+/// The file as written by the game masters. Relative paths are resolved from the working
+/// directory, like every other resource of the room.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    /// Whether plugging every fragment leads to the victory screen.
+    #[serde(default)]
+    victory_enabled: bool,
+    /// Name of the tab players return to after solving a puzzle.
+    home: String,
+    /// Time the group has to escape. Without it, the room isn't timed.
+    duration_minutes: Option<u64>,
+    /// Minimum time between two hints asked by the players.
+    #[serde(default)]
+    hint_cooldown_seconds: u64,
+    /// Taken off the timer for each hint asked by the players.
+    #[serde(default)]
+    hint_penalty_seconds: u64,
+    /// How the USB keys are detected.
+    #[serde(default)]
+    usb: UsbConfig,
+    /// Where the game master's control desk connects.
+    #[serde(default = "default_control_address")]
+    control_address: String,
+    /// Kept as raw tables so that an invalid tab can be reported with its own line.
+    #[serde(rename = "tab")]
+    tabs: Vec<Spanned<toml::Value>>,
+}
+
+fn default_control_address() -> String {
+    "127.0.0.1:7878".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum TabConfig {
+    Password {
+        password: Option<String>,
+        password_file: Option<PathBuf>,
+    },
+    Email {
+        folder: PathBuf,
+        /// What sending an email does, the first matching rule applying.
+        #[serde(default, rename = "rule")]
+        rules: Vec<Rule>,
+        /// How dates are shown, see `DateFormat`
+        date_format: Option<String>,
+        date_time_format: Option<String>,
+    },
+    Decrypt {
+        cipher: String,
+        answer: String,
+    },
+    Music {
+        file: PathBuf,
+        answers: Vec<String>,
+    },
+    TimeTrial {
+        #[serde(default)]
+        state: TimeTrialConfig,
+    },
+    SuccessfulInstall,
+    FailedInstall,
+    Victory,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimeTrialConfig {
+    /// Index of the calculation the players start on.
+    #[serde(default)]
+    calculation: usize,
+}
+
+/// The number of USB fragments hidden in the room.
+pub const FRAGMENT_COUNT: usize = 4;
+
+/// A room variant: the ordered tabs and the state each one starts in.
+pub struct Scenario {
+    pub tabs: Vec<TabUi>,
+    pub states: Vec<TabState>,
+    pub routes: Routes,
+    pub start_tab: usize,
+    pub victory_enabled: bool,
+    pub duration: Option<Duration>,
+    pub hints: Hints,
+    pub usb: UsbConfig,
+    pub control_address: String,
+}
+
+impl Scenario {
+    pub fn load(path: &Path, manager: &Manager) -> Result<Self> {
+        let (source, file) = read(path)?;
+
+        if file.tabs.is_empty() {
+            bail!("{}: the scenario declares no tabs", path.display());
+        }
+
+        let mut tabs = vec![];
+        let mut states = vec![];
+        let mut names = HashMap::new();
+        let mut fragments = vec![];
+        let mut hints = vec![];
+        for (index, tab) in file.tabs.into_iter().enumerate() {
+            let line = line_of(&source, tab.span().start);
+            let context = || format!("{}:{}: invalid tab", path.display(), line);
+
+            let toml::Value::Table(mut table) = tab.into_inner() else {
+                bail!("{}: a tab must be a table", context());
+            };
+            let name = table.remove("name").map(String::deserialize).transpose().with_context(context)?;
+            let fragment = table.remove("fragment").map(usize::deserialize).transpose().with_context(context)?;
+            hints.push(table.remove("hints").map(Vec::<String>::deserialize).transpose().with_context(context)?.unwrap_or_default());
+
+            if let Some(name) = name {
+                if names.insert(name.clone(), index).is_some() {
+                    bail!("{}: the name `{}` is already used", context(), name);
+                }
+            }
+            // Fragments are numbered like the labels of the keys, from 1
+            let fragment = match fragment {
+                Some(n @ 1..=FRAGMENT_COUNT) if fragments.contains(&Some(n - 1)) => {
+                    bail!("{}: fragment {} is already unlocked by another tab", context(), n);
+                }
+                Some(n @ 1..=FRAGMENT_COUNT) => Some(n - 1),
+                Some(n) => bail!("{}: there is no fragment {}", context(), n),
+                None => None,
+            };
+            fragments.push(fragment);
+
+            let (ui, state) = TabConfig::deserialize(toml::Value::Table(table))
+                .map_err(anyhow::Error::from)
+                .and_then(|config| build_tab(config, manager))
+                .with_context(context)?;
+            tabs.push(ui);
+            states.push(state);
+        }
+
+        for tab in &tabs {
+            if let TabUi::Email(program) = tab {
+                if let Some(name) = program.awaited_tabs().find(|&x| !names.contains_key(x)) {
+                    bail!("{}: an email waits for the tab `{}` to be solved, but there is no such tab", path.display(), name);
+                }
+                if let Some(name) = program.goto_tabs().find(|&x| !names.contains_key(x)) {
+                    bail!("{}: a rule goes to the tab `{}`, but there is no such tab", path.display(), name);
+                }
+            }
+        }
+
+        let home = *names.get(&file.home)
+            .with_context(|| format!("{}: there is no tab named `{}`", path.display(), file.home))?;
+        let victory = tabs.iter().position(|tab| matches!(tab, TabUi::Victory(_)));
+        if file.victory_enabled && victory.is_none() {
+            bail!("{}: the victory is enabled but there is no victory tab", path.display());
+        }
+
+        // A password tab without a password is skipped, so the room can start unlocked
+        let start_tab = match &tabs[0] {
+            TabUi::Password(pass) if pass.is_empty() && tabs.len() > 1 => 1,
+            _ => 0,
+        };
+
+        Ok(Scenario {
+            tabs,
+            states,
+            routes: Routes::new(names, fragments, home, victory),
+            start_tab,
+            victory_enabled: file.victory_enabled,
+            duration: file.duration_minutes.map(|x| Duration::from_secs(x * 60)),
+            hints: Hints::new(
+                hints,
+                Duration::from_secs(file.hint_cooldown_seconds),
+                Duration::from_secs(file.hint_penalty_seconds),
+            ),
+            usb: file.usb,
+            control_address: file.control_address,
+        })
+    }
+}
+
+fn read(path: &Path) -> Result<(String, ScenarioFile)> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("scenario {} not found", path.display()))?;
+    let file = toml::from_str(&source)
+        .with_context(|| format!("invalid scenario {}", path.display()))?;
+    Ok((source, file))
+}
+
+/// How the email tab showing `folder` writes its dates, so that what's printed looks the same.
+/// Emails no tab of the scenario shows get the default format.
+pub fn date_format(path: &Path, folder: &Path) -> Result<DateFormat> {
+    let (source, file) = read(path)?;
+    for tab in file.tabs {
+        let line = line_of(&source, tab.span().start);
+        let context = || format!("{}:{}: invalid tab", path.display(), line);
+
+        let toml::Value::Table(mut table) = tab.into_inner() else {
+            bail!("{}: a tab must be a table", context());
+        };
+        // Common to every kind of tab, see `Scenario::load`
+        for key in ["name", "fragment", "hints"] {
+            table.remove(key);
+        }
+        let config = TabConfig::deserialize(toml::Value::Table(table)).with_context(context)?;
+        if let TabConfig::Email { folder: shown, date_format, date_time_format, .. } = config {
+            if same_path(&shown, folder) {
+                return DateFormat::new(date_format, date_time_format).with_context(context);
+            }
+        }
+    }
+    Ok(DateFormat::default())
+}
+
+/// Whether the paths lead to the same place, however they are written.
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn build_tab(config: TabConfig, manager: &Manager) -> Result<(TabUi, TabState)> {
+    Ok(match config {
+        TabConfig::Password { password, password_file } => {
+            let password = match (password, password_file) {
+                (Some(password), None) => password,
+                (None, Some(file)) => String::from_utf8(
+                    std::fs::read(&file).with_context(|| format!("{} not found", file.display()))?
+                )?.trim().to_string(),
+                _ => bail!("exactly one of `password` and `password_file` must be given"),
+            };
+            (TabUi::Password(PasswordEntry::new(password)), TabState::Password(PasswordEntryState::new()))
+        }
+        TabConfig::Email { folder, rules, date_format, date_time_format } => {
+            let dates = DateFormat::new(date_format, date_time_format)?;
+            let email = EmailProgram::new(folder.clone(), rules, dates, manager.clone())
+                .with_context(|| format!("{} folder could not be loaded", folder.display()))?;
+            (TabUi::Email(email), TabState::Email(Box::new(EmailProgramState::new())))
+        }
+        TabConfig::Decrypt { cipher, answer } => {
+            (TabUi::Decrypt(Decrypt::new(cipher, answer)), TabState::Decrypt(DecryptState::new()))
+        }
+        TabConfig::Music { file, answers } => {
+            if !file.is_file() {
+                bail!("{} not found", file.display());
+            }
+            if answers.is_empty() {
+                bail!("a music tab needs at least one answer");
+            }
+            (TabUi::Music(MusicPlayer::new(file, answers, manager.clone())), TabState::Music(MusicPlayerState::new()))
+        }
+        TabConfig::TimeTrial { state } => {
+            let state = TimeTrialState::new(state.calculation)
+                .with_context(|| format!("there is no calculation {}", state.calculation))?;
+            (TabUi::TimeTrial(TimeTrial {}), TabState::TimeTrial(state))
+        }
+        TabConfig::SuccessfulInstall => {
+            (TabUi::SuccessfulInstall(SuccessfulInstall {}), TabState::SuccessfulInstall(SuccessfulInstallState::new()))
+        }
+        TabConfig::FailedInstall => {
+            (TabUi::FailedInstall(FailedInstall {}), TabState::FailedInstall(FailedInstallState::new()))
+        }
+        TabConfig::Victory => (TabUi::Victory(Victory {}), TabState::Victory(VictoryState::new())),
+    })
+}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
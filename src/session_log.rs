@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Context;
+use serde::Serialize;
+
+/// Everything worth knowing when debriefing a group, one JSON object per line.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Entry {
+    SessionStart { scenario: String },
+    Key { key: String, modifiers: String },
+    Attempt { puzzle: &'static str, answer: String, correct: bool },
+    Tab { from: usize, to: usize, kind: &'static str },
+    Transition { transition: String },
+    Usb { label: String, plugged: bool },
+    Command { command: String },
+    Error { message: String },
+    /// Something in the room's files looks wrong, but the game goes on.
+    Warning { message: String },
+    TimeUp,
+    Hint { tab: usize, number: usize, by_game_master: bool },
+    Delivery { subject: String },
+    /// `rule` counts from 1, like the rules in the scenario.
+    Sent { to: String, subject: String, body: String, rule: Option<usize> },
+    /// `subject` is that of the first email moved, `folder` the name of its directory.
+    Moved { subject: String, folder: String },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    /// Milliseconds since the Unix epoch
+    time: u128,
+    #[serde(flatten)]
+    entry: &'a Entry,
+}
+
+// Tabs log their attempts themselves, so the log is reachable from anywhere. Nothing is written
+// until a session is started.
+static LOG: Mutex<Option<LineWriter<File>>> = Mutex::new(None);
+
+/// Starts a new log file in `folder`, named after the current time.
+pub fn start(folder: &Path, scenario: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(folder).with_context(|| format!("could not create {}", folder.display()))?;
+    let path = folder.join(format!("session-{}.jsonl", now()));
+    let file = File::create(&path).with_context(|| format!("could not create {}", path.display()))?;
+
+    *LOG.lock().unwrap() = Some(LineWriter::new(file));
+    record(Entry::SessionStart { scenario: scenario.display().to_string() });
+    Ok(())
+}
+
+pub fn record(entry: Entry) {
+    let mut log = LOG.lock().unwrap();
+    let Some(writer) = log.as_mut() else {
+        return;
+    };
+
+    let line = Line { time: now(), entry: &entry };
+    if let Ok(json) = serde_json::to_string(&line) {
+        // Losing a line of log is better than stopping the game
+        let _ = writeln!(writer, "{}", json);
+    }
+}
+
+/// Records an answer given to a puzzle.
+pub fn attempt(puzzle: &'static str, answer: &str, correct: bool) {
+    record(Entry::Attempt { puzzle, answer: answer.to_string(), correct });
+}
+
+fn now() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_millis()).unwrap_or(0)
+}
@@ -0,0 +1,45 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::hints::HintProgress;
+use crate::scenario::FRAGMENT_COUNT;
+use crate::timer::{Stopwatch, Timer};
+use crate::TabState;
+
+/// The progress of a group, saved regularly so a crash or a reboot doesn't cost them their
+/// puzzles.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub scenario: PathBuf,
+    pub current_tab: usize,
+    pub history: Vec<usize>,
+    pub usbs_plugged: [bool; FRAGMENT_COUNT],
+    #[serde(default)]
+    pub timer: Option<Timer>,
+    #[serde(default)]
+    pub stopwatch: Stopwatch,
+    #[serde(default)]
+    pub solved: HashSet<usize>,
+    #[serde(default)]
+    pub hints: HintProgress,
+    pub states: Vec<TabState>,
+}
+
+impl Snapshot {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = std::fs::read(path).with_context(|| format!("no snapshot to resume from at {}", path.display()))?;
+        serde_json::from_slice(&file).with_context(|| format!("invalid snapshot {}", path.display()))
+    }
+
+    /// Writes the snapshot next to the previous one before replacing it, so that a crash while
+    /// saving never leaves a truncated file behind.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, serde_json::to_vec(self)?)
+            .with_context(|| format!("could not write {}", temporary.display()))?;
+        std::fs::rename(&temporary, path)
+            .with_context(|| format!("could not replace {}", path.display()))?;
+        Ok(())
+    }
+}
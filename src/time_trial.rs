@@ -1,4 +1,5 @@
-use crate::model::Tab;
+use crate::model::{Tab, Transition};
+use crate::session_log;
 use ratatui::buffer::Buffer;
 use ratatui::crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
 use ratatui::layout::{Layout, Margin, Rect};
@@ -9,6 +10,7 @@ use ratatui::text::ToLine;
 use ratatui::widgets::canvas::Canvas;
 use ratatui::widgets::{Block, Clear, Paragraph, StatefulWidgetRef, WidgetRef};
 use std::iter::{once, repeat};
+use serde::{Deserialize, Serialize};
 
 /*
 [x+y\*z], [(x+y)\*z], [x+y\*z], [x+y#{sym.div}z],
@@ -27,12 +29,23 @@ const CALCULATIONS : [(&str, i64, Color); 6] = [
     ("20++20", 40, Color::Green),
 ];
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TimeTrialState {
     Calculations(usize, String, bool),
     Connections([(usize, usize); 4], (usize, usize), Option<usize>),
 }
 
+impl TimeTrialState {
+    /// Starts the trial at the given calculation, if it exists.
+    pub fn new(calculation: usize) -> Option<Self> {
+        if calculation < CALCULATIONS.len() {
+            Some(TimeTrialState::Calculations(calculation, String::new(), false))
+        } else {
+            None
+        }
+    }
+}
+
 const CONNECTION_COLORS : [Color; 4] = [Color::Red, Color::White, Color::Green, Color::Blue];
 
 #[derive(Debug, Clone)]
@@ -152,7 +165,7 @@ impl StatefulWidgetRef for TimeTrial {
 }
 
 impl Tab for TimeTrial {
-    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<usize> {
+    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<Transition> {
         match state {
             TimeTrialState::Calculations(calc, text, err) => {
                 if let Event::Key(KeyEvent { code: KeyCode::Char(c), kind: KeyEventKind::Press, .. }) = event {
@@ -164,7 +177,9 @@ impl Tab for TimeTrial {
                     text.pop();
                     *err = false;
                 } else if let Event::Key(KeyEvent { code: KeyCode::Enter, kind: KeyEventKind::Press, .. }) = event {
-                    if *text == CALCULATIONS[*calc].1.to_string() {
+                    let correct = *text == CALCULATIONS[*calc].1.to_string();
+                    session_log::attempt("time_trial", text, correct);
+                    if correct {
                         if *calc == CALCULATIONS.len() - 1 {
                             *state = TimeTrialState::Connections([
                                 (11, 2),
@@ -172,6 +187,7 @@ impl Tab for TimeTrial {
                                 (54, 2),
                                 (74, 2),
                             ], (10, 10), None);
+                            return Some(Transition::Custom("time_trial:connections".to_string()));
                         } else {
                             *calc += 1;
                             text.clear();
@@ -216,8 +232,10 @@ impl Tab for TimeTrial {
                                         (18, 15),
                                         (40, 15),
                                     ];
-                                    if *pos == SOLUTION {
-                                        return Some(1);
+                                    let correct = *pos == SOLUTION;
+                                    session_log::attempt("time_trial", &format!("{:?}", pos), correct);
+                                    if correct {
+                                        return Some(Transition::Solved);
                                     }
 
                                     None
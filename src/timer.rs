@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// Counts down the time the group has left to escape.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timer {
+    left: Duration,
+    paused: bool,
+    // Not saved: a resumed session starts counting again from its first tick
+    #[serde(skip)]
+    last_tick: Option<Instant>,
+}
+
+impl Timer {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            left: duration,
+            paused: false,
+            last_tick: None,
+        }
+    }
+
+    /// Takes the time elapsed since the previous tick off the clock, unless paused.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        if let Some(last_tick) = self.last_tick {
+            if !self.paused {
+                self.left = self.left.saturating_sub(now - last_tick);
+            }
+        }
+        self.last_tick = Some(now);
+    }
+
+    /// Adds (bonus) or removes (penalty) seconds.
+    pub fn adjust(&mut self, seconds: i64) {
+        let change = Duration::from_secs(seconds.unsigned_abs());
+        self.left = if seconds >= 0 {
+            self.left + change
+        } else {
+            self.left.saturating_sub(change)
+        };
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn left(&self) -> Duration {
+        self.left
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.left.is_zero()
+    }
+}
+
+/// Counts the time played so far, leaving out the pauses.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Stopwatch {
+    elapsed: Duration,
+    paused: bool,
+    #[serde(skip)]
+    last_tick: Option<Instant>,
+}
+
+impl Stopwatch {
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        if let Some(last_tick) = self.last_tick {
+            if !self.paused {
+                self.elapsed += now - last_tick;
+            }
+        }
+        self.last_tick = Some(now);
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// A duration written with its unit, like `90s` or `15m`, as the scenario files and the game
+/// master write them.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    let (number, unit) = match text.strip_suffix('s') {
+        Some(seconds) => (seconds, 1),
+        None => (text.strip_suffix('m')?, 60),
+    };
+    // Signs are for the caller to handle
+    if !number.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let number: u64 = number.parse().ok()?;
+    Some(Duration::from_secs(number.checked_mul(unit)?))
+}
@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use anyhow::Context;
+use notify::event::{CreateKind, ModifyKind, RenameMode};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::Deserialize;
+use crate::scenario::FRAGMENT_COUNT;
+
+/// A key appearing or disappearing, by filesystem label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbEvent {
+    pub label: String,
+    pub plugged: bool,
+}
+
+impl UsbEvent {
+    /// The fragment on this key. Keys are labelled `1-ESCAPE` to `4-ESCAPE`.
+    pub fn fragment(&self) -> Option<usize> {
+        let number = self.label.strip_suffix("-ESCAPE")?.parse::<usize>().ok()?;
+        (1..=FRAGMENT_COUNT).contains(&number).then(|| number - 1)
+    }
+}
+
+pub trait UsbBackend {
+    /// Returns what changed since the last call. The first call also reports the keys that were
+    /// already plugged in.
+    fn poll(&mut self) -> Vec<UsbEvent>;
+}
+
+/// How the keys are detected, from the `[usb]` table of the scenario.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case", deny_unknown_fields)]
+pub enum UsbConfig {
+    /// Watches the labels udev links in `/dev/disk/by-label`.
+    Inotify {
+        #[serde(default = "default_label_folder")]
+        path: PathBuf,
+    },
+    /// Asks `lsblk` for the labels of the block devices, regularly.
+    Lsblk,
+    /// Every file in `path` is a plugged key, for rehearsing without hardware.
+    Simulated {
+        path: PathBuf,
+    },
+}
+
+impl Default for UsbConfig {
+    fn default() -> Self {
+        UsbConfig::Inotify { path: default_label_folder() }
+    }
+}
+
+fn default_label_folder() -> PathBuf {
+    PathBuf::from("/dev/disk/by-label")
+}
+
+pub fn open(config: &UsbConfig) -> anyhow::Result<Box<dyn UsbBackend>> {
+    Ok(match config {
+        UsbConfig::Inotify { path } => Box::new(InotifyBackend::new(path)?),
+        UsbConfig::Lsblk => Box::new(LsblkBackend::new()),
+        UsbConfig::Simulated { path } => Box::new(DirectoryBackend::new(path.clone())),
+    })
+}
+
+pub struct InotifyBackend {
+    // Dropping the watcher would stop the events
+    _watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    already_plugged: Vec<UsbEvent>,
+}
+
+impl InotifyBackend {
+    pub fn new(folder: &Path) -> anyhow::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(folder, RecursiveMode::NonRecursive)
+            .with_context(|| format!("could not watch {}", folder.display()))?;
+
+        // Listed after the watch starts, so that no key falls in between
+        let already_plugged = list_folder(folder).unwrap_or_default()
+            .into_iter()
+            .map(|label| UsbEvent { label, plugged: true })
+            .collect();
+
+        Ok(Self {
+            _watcher: watcher,
+            events: rx,
+            already_plugged,
+        })
+    }
+}
+
+impl UsbBackend for InotifyBackend {
+    fn poll(&mut self) -> Vec<UsbEvent> {
+        let mut events = std::mem::take(&mut self.already_plugged);
+        for event in self.events.try_iter().flatten() {
+            let (path, plugged) = match event.kind {
+                // udev creates the link under a temporary name, then renames it
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => (event.paths.get(1), true),
+                EventKind::Create(CreateKind::File | CreateKind::Any) => (event.paths.first(), true),
+                EventKind::Remove(_) => (event.paths.first(), false),
+                _ => continue,
+            };
+            if let Some(label) = path.and_then(|x| x.file_name()) {
+                events.push(UsbEvent { label: label.to_string_lossy().to_string(), plugged });
+            }
+        }
+        events
+    }
+}
+
+/// Turns a list of labels, read every so often, into events.
+struct LabelPoller {
+    known: HashSet<String>,
+    last_poll: Option<Instant>,
+}
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+impl LabelPoller {
+    fn new() -> Self {
+        Self {
+            known: HashSet::new(),
+            last_poll: None,
+        }
+    }
+
+    fn poll(&mut self, list: impl FnOnce() -> Option<HashSet<String>>) -> Vec<UsbEvent> {
+        if self.last_poll.is_some_and(|x| x.elapsed() < POLL_INTERVAL) {
+            return vec![];
+        }
+        self.last_poll = Some(Instant::now());
+
+        // When the labels can't be read, assume nothing changed
+        let Some(labels) = list() else {
+            return vec![];
+        };
+
+        let mut events: Vec<_> = labels.difference(&self.known)
+            .map(|label| UsbEvent { label: label.clone(), plugged: true })
+            .chain(self.known.difference(&labels).map(|label| UsbEvent { label: label.clone(), plugged: false }))
+            .collect();
+        events.sort_by(|a, b| a.label.cmp(&b.label));
+        self.known = labels;
+        events
+    }
+}
+
+pub struct LsblkBackend {
+    poller: LabelPoller,
+}
+
+impl LsblkBackend {
+    pub fn new() -> Self {
+        Self {
+            poller: LabelPoller::new(),
+        }
+    }
+}
+
+impl UsbBackend for LsblkBackend {
+    fn poll(&mut self) -> Vec<UsbEvent> {
+        self.poller.poll(|| {
+            let devices = lsblk::BlockDevice::list().ok()?;
+            Some(devices.into_iter().filter_map(|x| x.label).collect())
+        })
+    }
+}
+
+pub struct DirectoryBackend {
+    folder: PathBuf,
+    poller: LabelPoller,
+}
+
+impl DirectoryBackend {
+    pub fn new(folder: PathBuf) -> Self {
+        Self {
+            folder,
+            poller: LabelPoller::new(),
+        }
+    }
+}
+
+impl UsbBackend for DirectoryBackend {
+    fn poll(&mut self) -> Vec<UsbEvent> {
+        let folder = &self.folder;
+        self.poller.poll(|| list_folder(folder).map(HashSet::from_iter))
+    }
+}
+
+fn list_folder(folder: &Path) -> Option<Vec<String>> {
+    let entries = std::fs::read_dir(folder).ok()?;
+    Some(entries.flatten().map(|x| x.file_name().to_string_lossy().to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugged(label: &str) -> UsbEvent {
+        UsbEvent { label: label.to_string(), plugged: true }
+    }
+
+    fn unplugged(label: &str) -> UsbEvent {
+        UsbEvent { label: label.to_string(), plugged: false }
+    }
+
+    fn labels(labels: &[&str]) -> Option<HashSet<String>> {
+        Some(labels.iter().map(|x| x.to_string()).collect())
+    }
+
+    /// Polls `backend` until it reports something, which the filesystem takes a moment to do.
+    fn wait(backend: &mut impl UsbBackend) -> Vec<UsbEvent> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            let events = backend.poll();
+            if !events.is_empty() {
+                return events;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        vec![]
+    }
+
+    #[test]
+    fn fragments() {
+        assert_eq!(plugged("1-ESCAPE").fragment(), Some(0));
+        assert_eq!(plugged(&format!("{}-ESCAPE", FRAGMENT_COUNT)).fragment(), Some(FRAGMENT_COUNT - 1));
+        assert_eq!(plugged("0-ESCAPE").fragment(), None);
+        assert_eq!(plugged(&format!("{}-ESCAPE", FRAGMENT_COUNT + 1)).fragment(), None);
+        assert_eq!(plugged("1-escape").fragment(), None);
+        assert_eq!(plugged("VACANCES").fragment(), None);
+    }
+
+    #[test]
+    fn poller_diffs_the_labels() {
+        let mut poller = LabelPoller::new();
+        // The keys already there come first
+        assert_eq!(poller.poll(|| labels(&["2-ESCAPE", "1-ESCAPE"])), vec![plugged("1-ESCAPE"), plugged("2-ESCAPE")]);
+        // Too soon to look again
+        assert_eq!(poller.poll(|| panic!("polled too often")), vec![]);
+
+        poller.last_poll = None;
+        assert_eq!(poller.poll(|| labels(&["2-ESCAPE", "3-ESCAPE"])), vec![unplugged("1-ESCAPE"), plugged("3-ESCAPE")]);
+        poller.last_poll = None;
+        assert_eq!(poller.poll(|| labels(&["2-ESCAPE", "3-ESCAPE"])), vec![]);
+        // Labels that can't be read don't unplug anything
+        poller.last_poll = None;
+        assert_eq!(poller.poll(|| None), vec![]);
+        poller.last_poll = None;
+        assert_eq!(poller.poll(|| labels(&[])), vec![unplugged("2-ESCAPE"), unplugged("3-ESCAPE")]);
+    }
+
+    #[test]
+    fn directory() {
+        let folder = tempfile::tempdir().unwrap();
+        std::fs::write(folder.path().join("1-ESCAPE"), "").unwrap();
+        let mut backend = DirectoryBackend::new(folder.path().to_path_buf());
+        assert_eq!(backend.poll(), vec![plugged("1-ESCAPE")]);
+
+        std::fs::write(folder.path().join("2-ESCAPE"), "").unwrap();
+        std::fs::remove_file(folder.path().join("1-ESCAPE")).unwrap();
+        backend.poller.last_poll = None;
+        assert_eq!(backend.poll(), vec![unplugged("1-ESCAPE"), plugged("2-ESCAPE")]);
+
+        // A folder gone is like labels that can't be read
+        drop(folder);
+        backend.poller.last_poll = None;
+        assert_eq!(backend.poll(), vec![]);
+    }
+
+    #[test]
+    fn inotify() {
+        let folder = tempfile::tempdir().unwrap();
+        std::fs::write(folder.path().join("1-ESCAPE"), "").unwrap();
+        let mut backend = InotifyBackend::new(folder.path()).unwrap();
+        assert_eq!(backend.poll(), vec![plugged("1-ESCAPE")]);
+        assert_eq!(backend.poll(), vec![]);
+
+        // Like udev, which links the label under another name first
+        std::fs::write(folder.path().join(".tmp-2"), "").unwrap();
+        assert_eq!(wait(&mut backend), vec![plugged(".tmp-2")]);
+        std::fs::rename(folder.path().join(".tmp-2"), folder.path().join("2-ESCAPE")).unwrap();
+        assert!(wait(&mut backend).contains(&plugged("2-ESCAPE")));
+
+        std::fs::remove_file(folder.path().join("1-ESCAPE")).unwrap();
+        assert_eq!(wait(&mut backend), vec![unplugged("1-ESCAPE")]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::model::Tab;
+use crate::model::{Tab, Transition};
 use rand::{Rng, SeedableRng};
 use ratatui::buffer::Buffer;
 use ratatui::crossterm::event::Event;
@@ -7,8 +7,9 @@ use ratatui::prelude::{Color, Layout, Text, Widget};
 use ratatui::style::Style;
 use ratatui::widgets::StatefulWidgetRef;
 use ratatui::{crossterm, Frame};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VictoryState {
     // Each vec is 1 column
     grid: Vec<Vec<char>>,
@@ -39,7 +40,7 @@ impl StatefulWidgetRef for Victory {
 }
 
 impl Tab for Victory {
-    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<usize> {
+    fn handle_input(&self, event: Event, state: &mut Self::State) -> Option<Transition> {
         None
     }
 }